@@ -1,11 +1,18 @@
 #![no_main]
 use libfuzzer_sys::fuzz_target;
-use snapview_test_lib::Model;
+use snapview_test_lib::{Model, ModelError};
 
 fuzz_target!(|inputs: Vec<f64>| {
-    if let Ok(model) = Model::new(&inputs, f64::MAX) {
-        for input in inputs {
-            model.calculate_levels(input).unwrap();
+    match Model::new(&inputs, f64::MAX) {
+        Ok(model) => {
+            for input in inputs {
+                match model.calculate_levels(input) {
+                    Ok(_) | Err(ModelError::InvalidTime) | Err(ModelError::NegativeTime) | Err(ModelError::TimeOutOfHorizon) => {}
+                    Err(e) => panic!("{}", e),
+                }
+            }
         }
+        Err(ModelError::Inconsistency(reason)) => panic!("{}", reason),
+        Err(_) => {}
     }
 });
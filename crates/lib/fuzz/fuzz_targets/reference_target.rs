@@ -0,0 +1,17 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use snapview_test_lib::Model;
+
+fuzz_target!(|inputs: Vec<u16>| {
+    // keep the reference simulation small and the heights well within the precision of f64
+    if inputs.len() > 32 {
+        return;
+    }
+    let heights: Vec<f64> = inputs.iter().map(|height| *height as f64 / 100.0).collect();
+
+    if let Ok(model) = Model::new(&heights, 10.0) {
+        // the reference misplaces only a part of the rain of a step, when a route changes within it
+        let deviation = model.compare_with_reference(&[0.5, 1.0, 5.0, 10.0], 0.001).unwrap();
+        assert!(deviation.error() < 0.1, "{:?}", deviation);
+    }
+});
@@ -0,0 +1,288 @@
+use alloc::vec;
+use alloc::vec::Vec;
+use core::ops::Range;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::error::ModelError;
+use crate::model::Model;
+use crate::scalar::Scalar;
+
+const BISECTION_STEPS: usize = 64;
+
+/// Raises of the columns, which keep the protected columns dry until the deadline
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Barrier<T = f64> {
+    raises: Vec<(usize, T)>,
+    flood_time: Option<T>,
+}
+
+impl<T: Scalar> Barrier<T> {
+    /// Extra height for each raised column, ordered by the columns
+    pub fn raises(&self) -> &[(usize, T)] {
+        &self.raises
+    }
+
+    pub fn total_raise(&self) -> T {
+        self.raises.iter().fold(T::zero(), |acc, (_, raise)| acc + raise.clone())
+    }
+
+    /// Time when water first stands above the protected columns with the barrier,
+    /// None if they stay dry within the max time of the model or the deadline, whichever is later
+    pub fn flood_time(&self) -> Option<T> {
+        self.flood_time.clone()
+    }
+}
+
+struct Design<'a, T> {
+    model: &'a Model<T>,
+    ground: Vec<T>,
+    protected: Range<usize>,
+    candidates: Vec<usize>,
+    deadline: T,
+}
+
+impl<'a, T: Scalar> Design<'a, T> {
+    fn heights(&self, raises: &[T]) -> Vec<T> {
+        let mut heights = self.ground.clone();
+        for (column, raise) in self.candidates.iter().zip(raises) {
+            heights[*column] = heights[*column].clone() + raise.clone();
+        }
+        heights
+    }
+
+    fn flood_time(&self, raises: &[T], horizon: T) -> Result<Option<T>, ModelError> {
+        let model = self.model.rebuild(&self.heights(raises), horizon)?;
+
+        Ok(model.arrival_times()[self.protected.clone()]
+            .iter()
+            .flatten()
+            .fold(None, |acc: Option<T>, time| match acc {
+                Some(acc) if acc <= *time => Some(acc),
+                _ => Some(time.clone()),
+            }))
+    }
+
+    fn is_dry(&self, raises: &[T]) -> Result<bool, ModelError> {
+        Ok(self.flood_time(raises, self.deadline.clone())?.is_none_or(|time| time >= self.deadline))
+    }
+
+    /// Lowest raise of the candidate at the index keeping the columns dry, while other raises are fixed
+    ///
+    /// The bracket from no raise to the provided one is checked first,
+    /// the upper end should keep the columns dry
+    fn lower(&self, raises: &mut [T], idx: usize) -> Result<(), ModelError> {
+        let two = T::from_usize(2);
+        let mut low = T::zero();
+        let mut high = raises[idx].clone();
+
+        if !self.is_dry(raises)? {
+            return Err(ModelError::Infeasible);
+        }
+        raises[idx] = low.clone();
+        if self.is_dry(raises)? {
+            return Ok(());
+        }
+
+        for _ in 0..BISECTION_STEPS {
+            let middle = (low.clone() + high.clone()) / two.clone();
+            raises[idx] = middle.clone();
+            if self.is_dry(raises)? {
+                high = middle;
+            } else {
+                low = middle;
+            }
+        }
+
+        raises[idx] = high;
+        Ok(())
+    }
+
+    /// Lowest common crest level of all the candidates keeping the columns dry
+    fn common_crest(&self, crest: T) -> Result<Vec<T>, ModelError> {
+        let raises_to = |level: &T| -> Vec<T> {
+            self.candidates.iter()
+                .map(|column| {
+                    let raise = level.clone() - self.ground[*column].clone();
+                    if raise.is_negative() { T::zero() } else { raise }
+                })
+                .collect()
+        };
+
+        let two = T::from_usize(2);
+        let mut low = T::zero();
+        let mut high = crest;
+
+        if !self.is_dry(&raises_to(&high))? {
+            return Err(ModelError::Infeasible);
+        }
+        if self.is_dry(&raises_to(&low))? {
+            return Ok(raises_to(&low));
+        }
+
+        for _ in 0..BISECTION_STEPS {
+            let middle = (low.clone() + high.clone()) / two.clone();
+            if self.is_dry(&raises_to(&middle))? {
+                high = middle;
+            } else {
+                low = middle;
+            }
+        }
+
+        Ok(raises_to(&high))
+    }
+}
+
+impl<T: Scalar> Model<T> {
+    /// Find extra heights at the candidate columns, which keep the protected columns
+    /// free of standing water until the deadline, as small as the search allows
+    ///
+    /// Raising a single candidate is preferred, the one needing the lowest raise wins.
+    /// If no candidate suffices alone, all of them are raised to a common crest,
+    /// then each one is lowered as long as the columns stay dry. The result of the latter
+    /// is not guaranteed to be the minimum. Heights are found by bisection, each raise is
+    /// the dry upper end of the final bracket, so it is an upper bound of the minimal raise
+    /// exceeding it by no more than the width of the bracket, the crest divided by 2^64.
+    ///
+    /// The bisection assumes that the columns, which stay dry with some raise, stay dry with any
+    /// higher one. Raising a wall may send the runoff into another basin, so it does not hold
+    /// for every terrain. Ends of each bisection are checked, so the returned barrier always
+    /// keeps the columns dry, but it may be far from the minimum, when the assumption fails.
+    pub fn design_barrier(&self, protected: Range<usize>, deadline: T, candidates: &[usize]) -> Result<Barrier<T>, ModelError> {
+        let ground = self.ground_heights();
+        let num_columns = ground.len();
+        if protected.start >= protected.end || protected.end > num_columns {
+            return Err(ModelError::RangeOutOfBounds { range: protected, num_columns });
+        }
+        if let Some(column) = candidates.iter().find(|column| **column >= num_columns) {
+            return Err(ModelError::RangeOutOfBounds { range: *column..*column + 1, num_columns });
+        }
+        if deadline.partial_cmp(&T::zero()).is_none() || !deadline.is_finite() {
+            return Err(ModelError::InvalidTime);
+        }
+        if deadline.is_negative() {
+            return Err(ModelError::NegativeTime);
+        }
+
+        let mut candidates = candidates.to_vec();
+        candidates.sort_unstable();
+        candidates.dedup();
+
+        // no wall gets covered, when it is higher than all the rain piled on the highest column
+        let highest = ground.iter().fold(T::zero(), |acc, height| if *height > acc { height.clone() } else { acc });
+        let crest = highest + self.config().rain_rate.clone() * deadline.clone() * T::from_usize(num_columns) + T::from_usize(1);
+
+        let design = Design {
+            model: self,
+            ground,
+            protected,
+            candidates,
+            deadline,
+        };
+        let horizon = if design.deadline > *self.max_time() { design.deadline.clone() } else { self.max_time().clone() };
+
+        let none = vec![T::zero(); design.candidates.len()];
+        let raises = if design.is_dry(&none)? {
+            none
+        } else {
+            let walls: Vec<T> = design.candidates.iter().map(|column| crest.clone() - design.ground[*column].clone()).collect();
+            if !design.is_dry(&walls)? {
+                return Err(ModelError::Infeasible);
+            }
+
+            let mut best: Option<(Vec<T>, T)> = None;
+            for idx in 0..design.candidates.len() {
+                let mut raises = none.clone();
+                raises[idx] = walls[idx].clone();
+                if !design.is_dry(&raises)? {
+                    continue;
+                }
+
+                design.lower(&mut raises, idx)?;
+                let total = raises[idx].clone();
+                if best.as_ref().is_none_or(|(_, best_total)| total < *best_total) {
+                    best = Some((raises, total));
+                }
+            }
+
+            match best {
+                Some((raises, _)) => raises,
+                None => {
+                    let mut raises = design.common_crest(crest)?;
+                    for idx in 0..raises.len() {
+                        design.lower(&mut raises, idx)?;
+                    }
+                    raises
+                }
+            }
+        };
+
+        let flood_time = design.flood_time(&raises, horizon)?;
+
+        Ok(Barrier {
+            raises: design.candidates.iter()
+                .cloned()
+                .zip(raises)
+                .filter(|(_, raise)| *raise > T::zero())
+                .collect(),
+            flood_time,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_abs_diff_eq;
+
+    use super::*;
+
+    #[test]
+    fn test_single_candidate() {
+        let model = Model::new(&[3.0, 1.0, 6.0, 4.0, 8.0, 9.0], 20.0).unwrap();
+
+        // the slope at 3.0 gets covered at 2 / 3, soon after the right lake spills over the wall at 6.0,
+        // to delay it until 0.75 the wall has to hold the right lake until 5 / 7, i.e. up to 4 + 3.5 * 5 / 7
+        let barrier = model.design_barrier(0..1, 0.75, &[4, 2]).unwrap();
+        assert_eq!(barrier.raises().len(), 1);
+        assert_eq!(barrier.raises()[0].0, 2);
+        assert_abs_diff_eq!(barrier.raises()[0].1, 0.5, epsilon = 1e-9);
+        assert_abs_diff_eq!(barrier.total_raise(), 0.5, epsilon = 1e-9);
+        assert!(barrier.flood_time().unwrap() >= 0.75);
+
+        // a slightly lower wall does not hold the lake long enough
+        let lower = Model::new(&[3.0, 1.0, 6.0 + barrier.raises()[0].1 - 1e-6, 4.0, 8.0, 9.0], 20.0).unwrap();
+        assert!(lower.arrival_times()[0].unwrap() < 0.75);
+    }
+
+    #[test]
+    fn test_already_dry() {
+        let model = Model::new(&[3.0, 1.0, 6.0, 4.0, 8.0, 9.0], 20.0).unwrap();
+
+        let barrier = model.design_barrier(0..1, 0.5, &[2]).unwrap();
+        assert!(barrier.raises().is_empty());
+        assert_abs_diff_eq!(barrier.flood_time().unwrap(), 2.0 / 3.0, epsilon = 1e-12);
+    }
+
+    #[test]
+    fn test_errors() {
+        let model = Model::new(&[3.0, 1.0, 6.0, 4.0, 8.0, 9.0], 20.0).unwrap();
+
+        // the basin is wet right away, no matter how high the walls around are
+        assert_eq!(model.design_barrier(1..2, 1.0, &[0, 2]), Err(ModelError::Infeasible));
+        // the slope at 3.0 collects enough rain from the left basin itself
+        assert_eq!(model.design_barrier(0..1, 1.0, &[2]), Err(ModelError::Infeasible));
+
+        assert_eq!(
+            model.design_barrier(2..2, 1.0, &[2]),
+            Err(ModelError::RangeOutOfBounds { range: 2..2, num_columns: 6 }),
+        );
+        assert_eq!(
+            model.design_barrier(0..1, 1.0, &[6]),
+            Err(ModelError::RangeOutOfBounds { range: 6..7, num_columns: 6 }),
+        );
+        assert_eq!(model.design_barrier(0..1, f64::NAN, &[2]), Err(ModelError::InvalidTime));
+        assert_eq!(model.design_barrier(0..1, -1.0, &[2]), Err(ModelError::NegativeTime));
+    }
+}
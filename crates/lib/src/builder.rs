@@ -0,0 +1,196 @@
+use alloc::vec::Vec;
+
+use crate::config::Config;
+use crate::error::ModelError;
+use crate::model::Model;
+use crate::scalar::Scalar;
+use crate::tolerance::Tolerance;
+
+/// Builder of the `Model`
+///
+/// All the options are validated up front, before any calculation starts
+#[derive(Debug, Clone)]
+pub struct ModelBuilder<T = f64> {
+    heights: Vec<T>,
+    max_time: Option<T>,
+    config: Config<T>,
+}
+
+fn is_positive_number<T: Scalar>(v: &T) -> bool {
+    v.is_finite() && !v.is_negative()
+}
+
+impl<T: Scalar> ModelBuilder<T> {
+    pub fn new() -> Self {
+        ModelBuilder {
+            heights: Vec::new(),
+            max_time: None,
+            config: Config::default(),
+        }
+    }
+
+    /// Heights of the terrain columns
+    pub fn heights(mut self, heights: &[T]) -> Self {
+        self.heights = heights.to_vec();
+        self
+    }
+
+    /// Max time for which levels may be calculated
+    pub fn max_time(mut self, max_time: T) -> Self {
+        self.max_time = Some(max_time);
+        self
+    }
+
+    /// Policy to consider two heights equal,
+    /// `f64::EPSILON` absolute tolerance is used for `f64` by default
+    ///
+    /// Each run of columns within the tolerance of its first column is joined into a single part
+    /// at their mean height, which keeps the volume of the terrain. So `Model::calculate_levels`
+    /// reports the mean for these columns from the start rather than their input heights.
+    pub fn merge_tolerance(mut self, tolerance: Tolerance<T>) -> Self {
+        self.config.tolerance = tolerance;
+        self
+    }
+
+    /// Policy to consider two event times equal, such events happen at the earliest of them,
+    /// `f64::EPSILON` absolute tolerance is used for `f64` by default
+    pub fn time_tolerance(mut self, tolerance: Tolerance<T>) -> Self {
+        self.config.time_tolerance = tolerance;
+        self
+    }
+
+    /// Amount of water falling on each column per unit of time, 1 by default
+    pub fn rain_rate(mut self, rain_rate: T) -> Self {
+        self.config.rain_rate = rain_rate;
+        self
+    }
+
+    pub fn build(self) -> Result<Model<T>, ModelError> {
+        if self.heights.is_empty() {
+            return Err(ModelError::EmptyInput);
+        }
+
+        if let Some(index) = self.heights.iter().position(|item| !is_positive_number(item)) {
+            return Err(ModelError::InvalidHeight { index });
+        }
+
+        let max_time = self.max_time.ok_or(ModelError::MissingMaxTime)?;
+        if !is_positive_number(&max_time) {
+            return Err(ModelError::InvalidMaxTime);
+        }
+
+        if !self.config.tolerance.is_valid() || !self.config.time_tolerance.is_valid() {
+            return Err(ModelError::InvalidTolerance);
+        }
+
+        if !is_positive_number(&self.config.rain_rate) {
+            return Err(ModelError::InvalidRainRate);
+        }
+
+        Model::from_config(&self.heights, max_time, self.config)
+    }
+}
+
+impl<T: Scalar> Default for ModelBuilder<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_abs_diff_eq;
+
+    use super::*;
+
+    #[test]
+    fn test_validation() {
+        let builder = ModelBuilder::new().heights(&[1.0, 2.0]).max_time(1.0);
+
+        assert_eq!(ModelBuilder::<f64>::new().max_time(1.0).build().unwrap_err(), ModelError::EmptyInput);
+        assert_eq!(ModelBuilder::new().heights(&[1.0]).build().unwrap_err(), ModelError::MissingMaxTime);
+        assert_eq!(builder.clone().max_time(f64::NAN).build().unwrap_err(), ModelError::InvalidMaxTime);
+        assert_eq!(builder.clone().max_time(-1.0).build().unwrap_err(), ModelError::InvalidMaxTime);
+        assert_eq!(builder.clone().max_time(f64::INFINITY).build().unwrap_err(), ModelError::InvalidMaxTime);
+        assert_eq!(builder.clone().merge_tolerance(Tolerance::Absolute(-1e-9)).build().unwrap_err(), ModelError::InvalidTolerance);
+        assert_eq!(builder.clone().time_tolerance(Tolerance::Relative(f64::NAN)).build().unwrap_err(), ModelError::InvalidTolerance);
+        assert_eq!(builder.clone().rain_rate(f64::NAN).build().unwrap_err(), ModelError::InvalidRainRate);
+        assert_eq!(builder.clone().heights(&[1.0, f64::INFINITY]).build().unwrap_err(), ModelError::InvalidHeight { index: 1 });
+        assert!(builder.build().is_ok());
+    }
+
+    #[test]
+    fn test_rain_rate() {
+        let heights = [3.0, 1.0, 6.0, 4.0, 8.0, 9.0];
+        let model = Model::new(&heights, 20.0).unwrap();
+        let doubled = ModelBuilder::new()
+            .heights(&heights)
+            .max_time(10.0)
+            .rain_rate(2.0)
+            .build()
+            .unwrap();
+
+        for time in [0.0, 0.3, 1.0, 2.5, 10.0] {
+            let expected = model.calculate_levels(time * 2.0).unwrap();
+            for (expected, actual) in expected.iter().zip(doubled.calculate_levels(time).unwrap()) {
+                assert_abs_diff_eq!(*expected, actual, epsilon = 1e-9);
+            }
+        }
+
+        let dry = ModelBuilder::new().heights(&heights).max_time(10.0).rain_rate(0.0).build().unwrap();
+        assert_eq!(dry.calculate_levels(10.0).unwrap(), heights);
+    }
+
+    #[test]
+    fn test_merge_tolerance() {
+        let model = ModelBuilder::new()
+            .heights(&[1.0, 1.05, 3.0])
+            .max_time(1.0)
+            .merge_tolerance(Tolerance::Absolute(0.1))
+            .build()
+            .unwrap();
+
+        // the joined columns report their mean height rather than the input ones
+        let levels = model.calculate_levels(0.0).unwrap();
+        assert_abs_diff_eq!(levels[0], 1.025, epsilon = 1e-12);
+        assert_abs_diff_eq!(levels[1], 1.025, epsilon = 1e-12);
+        assert_eq!(levels[2], 3.0);
+
+        let model = ModelBuilder::new()
+            .heights(&[1000.0, 1000.5, 3000.0])
+            .max_time(1.0)
+            .merge_tolerance(Tolerance::Relative(1e-3))
+            .build()
+            .unwrap();
+
+        let levels = model.calculate_levels(0.0).unwrap();
+        assert_abs_diff_eq!(levels[0], levels[1]);
+
+        assert_eq!(
+            ModelBuilder::new().heights(&[1.0]).max_time(1.0).merge_tolerance(Tolerance::Relative(f64::NAN)).build().unwrap_err(),
+            ModelError::InvalidTolerance,
+        );
+    }
+
+    #[test]
+    fn test_merge_tolerance_volume() {
+        let heights = [1000.0, 1000.5, 999.7, 10.0, 500.0, 500.4, 2000.0, 1999.0];
+        let model = ModelBuilder::new()
+            .heights(&heights)
+            .max_time(1000.0)
+            .merge_tolerance(Tolerance::Relative(1e-3))
+            .build()
+            .unwrap();
+
+        // nearly equal columns are joined at their mean height, wherever they are
+        let levels = model.calculate_levels(0.0).unwrap();
+        assert_abs_diff_eq!(levels[0], 3000.2 / 3.0, epsilon = 1e-9);
+        assert_abs_diff_eq!(levels[7], 1999.5, epsilon = 1e-9);
+
+        let ground: f64 = heights.iter().sum();
+        for time in [0.0, 1.0, 50.0, 200.0, 700.0, 1000.0] {
+            let volume: f64 = model.calculate_levels(time).unwrap().iter().sum::<f64>() - ground;
+            assert_abs_diff_eq!(volume, 8.0 * time, epsilon = 1e-6);
+        }
+    }
+}
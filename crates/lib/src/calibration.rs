@@ -0,0 +1,526 @@
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cmp::Ordering;
+use core::slice;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::error::ModelError;
+use crate::model::Model;
+use crate::scalar::Scalar;
+
+const MAX_ITERATIONS: usize = 100;
+
+/// Level of water measured at a column at some time
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Observation<T = f64> {
+    time: T,
+    column: usize,
+    level: T,
+}
+
+impl<T: Scalar> Observation<T> {
+    pub fn new(time: T, column: usize, level: T) -> Self {
+        Observation { time, column, level }
+    }
+
+    pub fn time(&self) -> T {
+        self.time.clone()
+    }
+
+    pub fn column(&self) -> usize {
+        self.column
+    }
+
+    pub fn level(&self) -> T {
+        self.level.clone()
+    }
+}
+
+/// Rain rate fitted to the observations
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct RainFit<T = f64> {
+    starts: Vec<T>,
+    rates: Vec<T>,
+    residuals: Vec<T>,
+}
+
+impl<T: Scalar> RainFit<T> {
+    /// Start time of each interval of constant rain, the first one is always zero
+    pub fn starts(&self) -> &[T] {
+        &self.starts
+    }
+
+    /// Rain rate within each interval
+    pub fn rates(&self) -> &[T] {
+        &self.rates
+    }
+
+    /// Rain rate at the time
+    pub fn rate_at(&self, time: &T) -> T {
+        let idx = self.starts.partition_point(|start| start <= time).saturating_sub(1);
+        self.rates.get(idx).cloned().unwrap_or_else(T::zero)
+    }
+
+    /// Observed minus fitted level for each observation, in the order they were provided
+    pub fn residuals(&self) -> &[T] {
+        &self.residuals
+    }
+
+    pub fn sum_of_squares(&self) -> T {
+        sum_of_squares(&self.residuals)
+    }
+}
+
+fn sum_of_squares<T: Scalar>(residuals: &[T]) -> T {
+    residuals.iter().fold(T::zero(), |acc, residual| acc + residual.clone() * residual.clone())
+}
+
+fn abs<T: Scalar>(v: T) -> T {
+    if v.is_negative() {
+        T::zero() - v
+    } else {
+        v
+    }
+}
+
+/// Solve the linear system with Gaussian elimination, None for singular systems
+fn solve<T: Scalar>(mut matrix: Vec<Vec<T>>, mut rhs: Vec<T>) -> Option<Vec<T>> {
+    let n = rhs.len();
+
+    for col in 0..n {
+        let pivot = (col..n).max_by(|a, b| {
+            abs(matrix[*a][col].clone())
+                .partial_cmp(&abs(matrix[*b][col].clone()))
+                .unwrap_or(Ordering::Equal)
+        })?;
+        if matrix[pivot][col] == T::zero() {
+            return None;
+        }
+        matrix.swap(col, pivot);
+        rhs.swap(col, pivot);
+
+        for row in col + 1..n {
+            let (upper, lower) = matrix.split_at_mut(row);
+            let (pivot_row, target) = (&upper[col], &mut lower[0]);
+            let factor = target[col].clone() / pivot_row[col].clone();
+            for (value, pivot_value) in target[col..].iter_mut().zip(pivot_row[col..].iter()) {
+                *value = value.clone() - factor.clone() * pivot_value.clone();
+            }
+            rhs[row] = rhs[row].clone() - factor * rhs[col].clone();
+        }
+    }
+
+    let mut solution = vec![T::zero(); n];
+    for row in (0..n).rev() {
+        let known = (row + 1..n).fold(T::zero(), |acc, k| acc + matrix[row][k].clone() * solution[k].clone());
+        solution[row] = (rhs[row].clone() - known) / matrix[row][row].clone();
+    }
+
+    Some(solution)
+}
+
+/// Observations along with the model, which reproduces any rain schedule
+///
+/// Levels depend only on the amount of water fallen so far, so the levels under
+/// a varying rain are the levels of the model at the time it receives the same amount.
+struct Problem<'a, T> {
+    model: &'a Model<T>,
+    observations: &'a [Observation<T>],
+    starts: Vec<T>,
+    max_rain: T,
+}
+
+impl<'a, T: Scalar> Problem<'a, T> {
+    fn new(model: &'a Model<T>, observations: &'a [Observation<T>], breakpoints: &[T]) -> Result<Self, ModelError> {
+        if observations.is_empty() {
+            return Err(ModelError::NoObservations);
+        }
+
+        let num_columns = model.initial_parts().num_columns();
+        for (index, observation) in observations.iter().enumerate() {
+            let is_valid_time = observation.time.is_finite() && !observation.time.is_negative();
+            if !is_valid_time || observation.column >= num_columns || !observation.level.is_finite() {
+                return Err(ModelError::InvalidObservation { index });
+            }
+        }
+
+        let mut starts = vec![T::zero()];
+        for breakpoint in breakpoints {
+            let is_increasing = starts.last().is_some_and(|last| *last < *breakpoint);
+            if !breakpoint.is_finite() || !is_increasing {
+                return Err(ModelError::InvalidBreakpoints);
+            }
+            starts.push(breakpoint.clone());
+        }
+
+        let rain_rate = model.config().rain_rate.clone();
+        if rain_rate <= T::zero() {
+            return Err(ModelError::InvalidRainRate);
+        }
+
+        Ok(Problem {
+            model,
+            observations,
+            starts,
+            max_rain: rain_rate * model.max_time().clone(),
+        })
+    }
+
+    fn last_time(&self) -> T {
+        self.observations.iter()
+            .map(|observation| observation.time.clone())
+            .fold(T::zero(), |acc, time| if time > acc { time } else { acc })
+    }
+
+    /// Time the rain falls within each interval up to the time
+    fn exposures(&self, time: &T) -> Vec<T> {
+        self.starts.iter()
+            .enumerate()
+            .map(|(idx, start)| {
+                if time <= start {
+                    return T::zero();
+                }
+
+                match self.starts.get(idx + 1) {
+                    Some(end) if end < time => end.clone() - start.clone(),
+                    _ => time.clone() - start.clone(),
+                }
+            })
+            .collect()
+    }
+
+    fn rain(&self, rates: &[T], time: &T) -> T {
+        self.exposures(time)
+            .into_iter()
+            .zip(rates.iter())
+            .fold(T::zero(), |acc, (exposure, rate)| acc + exposure * rate.clone())
+    }
+
+    /// Level at the column after the amount of rain along with its derivative by the amount
+    fn level_at_rain(&self, column: usize, rain: &T) -> Result<(T, T), ModelError> {
+        let rain_rate = self.model.config().rain_rate.clone();
+        let time = rain.clone() / rain_rate.clone();
+        let time = if time > *self.model.max_time() { self.model.max_time().clone() } else { time };
+
+        let (offset, parts) = self.model.generation_at(time)?;
+        let idx = parts.as_ref().partition_point(|part| part.range().end <= column);
+        let (part, (velocity, num_columns)) = parts.as_ref().get(idx)
+            .zip(parts.velocities().get(idx))
+            .ok_or(ModelError::Inconsistency("no part covers the column"))?;
+
+        let rate = velocity.clone() / T::from_usize(*num_columns);
+        Ok((part.height() + rate.clone() * offset, rate / rain_rate))
+    }
+
+    /// Residuals of the observations along with the derivatives of the levels by the amount of rain
+    fn residuals(&self, rates: &[T]) -> Result<(Vec<T>, Vec<T>), ModelError> {
+        let mut residuals = Vec::with_capacity(self.observations.len());
+        let mut slopes = Vec::with_capacity(self.observations.len());
+
+        for observation in self.observations {
+            let (level, slope) = self.level_at_rain(observation.column, &self.rain(rates, &observation.time))?;
+            residuals.push(observation.level.clone() - level);
+            slopes.push(slope);
+        }
+
+        Ok((residuals, slopes))
+    }
+
+    fn cost(&self, rates: &[T]) -> Result<T, ModelError> {
+        Ok(sum_of_squares(&self.residuals(rates)?.0))
+    }
+
+    /// Keep the rates non-negative and the rain within the horizon of the model
+    fn project(&self, rates: Vec<T>) -> Vec<T> {
+        let rates: Vec<T> = rates.into_iter()
+            .map(|rate| if rate.is_negative() { T::zero() } else { rate })
+            .collect();
+
+        let rain = self.rain(&rates, &self.last_time());
+        if rain <= self.max_rain {
+            return rates;
+        }
+
+        let scale = self.max_rain.clone() / rain;
+        rates.into_iter().map(|rate| rate * scale.clone()).collect()
+    }
+
+    /// Find the best constant rate
+    ///
+    /// Levels are piecewise linear in the rate between the rates at which
+    /// any observed column goes through a merge, so each piece is solved exactly
+    fn fit_constant(&self) -> Result<T, ModelError> {
+        let last_time = self.last_time();
+        if last_time <= T::zero() {
+            return Ok(T::zero());
+        }
+        let max_rate = self.max_rain.clone() / last_time;
+        let rain_rate = self.model.config().rain_rate.clone();
+
+        let mut candidates = vec![T::zero(), max_rate.clone()];
+        for generation in self.model.generations() {
+            let rain = generation.start.clone() * rain_rate.clone();
+            for observation in self.observations {
+                if observation.time > T::zero() {
+                    let rate = rain.clone() / observation.time.clone();
+                    if rate > T::zero() && rate < max_rate {
+                        candidates.push(rate);
+                    }
+                }
+            }
+        }
+        candidates.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+        candidates.dedup();
+
+        let two = T::from_usize(2);
+        let mut best = (T::zero(), self.cost(&[T::zero()])?);
+        for pair in candidates.windows(2) {
+            let (low, high) = (&pair[0], &pair[1]);
+            let middle = (low.clone() + high.clone()) / two.clone();
+
+            let (residuals, slopes) = self.residuals(slice::from_ref(&middle))?;
+            let (numerator, denominator) = residuals.into_iter()
+                .zip(slopes)
+                .zip(self.observations.iter())
+                .fold((T::zero(), T::zero()), |(numerator, denominator), ((residual, slope), observation)| {
+                    let gradient = slope * observation.time.clone();
+                    (numerator + gradient.clone() * residual, denominator + gradient.clone() * gradient)
+                });
+
+            let rate = if denominator > T::zero() {
+                let rate = middle + numerator / denominator;
+                if rate < *low {
+                    low.clone()
+                } else if rate > *high {
+                    high.clone()
+                } else {
+                    rate
+                }
+            } else {
+                middle
+            };
+
+            let cost = self.cost(slice::from_ref(&rate))?;
+            if cost < best.1 {
+                best = (rate, cost);
+            }
+        }
+
+        Ok(best.0)
+    }
+
+    /// Refine the rates with Levenberg-Marquardt, starting from the constant rate
+    fn fit_piecewise(&self) -> Result<Vec<T>, ModelError> {
+        let constant = self.fit_constant()?;
+        let mut rates = vec![constant; self.starts.len()];
+        let mut best = self.cost(&rates)?;
+
+        let ten = T::from_usize(10);
+        let max_damping = T::from_usize(1_000_000_000);
+        let mut damping = T::from_usize(1) / T::from_usize(1000);
+
+        for _ in 0..MAX_ITERATIONS {
+            if damping > max_damping {
+                break;
+            }
+
+            let (residuals, slopes) = self.residuals(&rates)?;
+            let jacobian: Vec<Vec<T>> = self.observations.iter()
+                .zip(slopes)
+                .map(|(observation, slope)| {
+                    self.exposures(&observation.time)
+                        .into_iter()
+                        .map(|exposure| exposure * slope.clone())
+                        .collect()
+                })
+                .collect();
+
+            let n = rates.len();
+            let mut normal = vec![vec![T::zero(); n]; n];
+            let mut gradient = vec![T::zero(); n];
+            for (row, residual) in jacobian.iter().zip(residuals.iter()) {
+                for i in 0..n {
+                    gradient[i] = gradient[i].clone() + row[i].clone() * residual.clone();
+                    for j in 0..n {
+                        normal[i][j] = normal[i][j].clone() + row[i].clone() * row[j].clone();
+                    }
+                }
+            }
+            for (i, row) in normal.iter_mut().enumerate() {
+                row[i] = row[i].clone() + damping.clone() * (row[i].clone() + T::from_usize(1));
+            }
+
+            let step = match solve(normal, gradient) {
+                Some(step) => step,
+                None => {
+                    damping = damping * ten.clone();
+                    continue;
+                }
+            };
+
+            let candidate = self.project(rates.iter().zip(step).map(|(rate, step)| rate.clone() + step).collect());
+            let cost = self.cost(&candidate)?;
+            if cost < best {
+                rates = candidate;
+                best = cost;
+                damping = damping / ten.clone();
+            } else {
+                damping = damping * ten.clone();
+            }
+        }
+
+        Ok(rates)
+    }
+
+    fn into_fit(self, rates: Vec<T>) -> Result<RainFit<T>, ModelError> {
+        let (residuals, _) = self.residuals(&rates)?;
+
+        Ok(RainFit {
+            starts: self.starts,
+            rates,
+            residuals,
+        })
+    }
+}
+
+impl<T: Scalar> Model<T> {
+    /// Estimate the constant rain rate, which produces the observed levels
+    /// with the least sum of squared residuals
+    ///
+    /// Heights of the model are used as is and its own rain rate only sets
+    /// the horizon: the fitted rain may not exceed the amount the model receives by its max time.
+    pub fn fit_constant_rain(&self, observations: &[Observation<T>]) -> Result<RainFit<T>, ModelError> {
+        let problem = Problem::new(self, observations, &[])?;
+        let rate = problem.fit_constant()?;
+
+        problem.into_fit(vec![rate])
+    }
+
+    /// Estimate the rain rate, which is constant between the breakpoints
+    ///
+    /// Intervals with no observations after their start keep the rate of the constant fit,
+    /// since the observations do not depend on them
+    pub fn fit_piecewise_rain(&self, observations: &[Observation<T>], breakpoints: &[T]) -> Result<RainFit<T>, ModelError> {
+        let problem = Problem::new(self, observations, breakpoints)?;
+        let rates = problem.fit_piecewise()?;
+
+        problem.into_fit(rates)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_abs_diff_eq;
+
+    use crate::ModelBuilder;
+
+    use super::*;
+
+    const HEIGHTS: [f64; 6] = [3.0, 1.0, 6.0, 4.0, 8.0, 9.0];
+
+    fn observe(model: &Model, rain: impl Fn(f64) -> f64) -> Vec<Observation> {
+        let mut observations = Vec::new();
+        for step in 1..=20 {
+            let time = step as f64 * 0.25;
+            let levels = model.calculate_levels(rain(time)).unwrap();
+            for column in [1, 3, 5] {
+                observations.push(Observation::new(time, column, levels[column]));
+            }
+        }
+        observations
+    }
+
+    #[test]
+    fn test_constant() {
+        let model = Model::new(&HEIGHTS, 100.0).unwrap();
+        let observations = observe(&model, |time| 2.5 * time);
+
+        let fit = model.fit_constant_rain(&observations).unwrap();
+        assert_eq!(fit.starts(), &[0.0]);
+        assert_abs_diff_eq!(fit.rates()[0], 2.5, epsilon = 1e-9);
+        assert_abs_diff_eq!(fit.rate_at(&3.0), 2.5, epsilon = 1e-9);
+        assert_eq!(fit.residuals().len(), observations.len());
+        assert_abs_diff_eq!(fit.sum_of_squares(), 0.0, epsilon = 1e-12);
+
+        // the rain rate of the model does not matter
+        let scaled = ModelBuilder::new().heights(&HEIGHTS).max_time(50.0).rain_rate(2.0).build().unwrap();
+        let fit = scaled.fit_constant_rain(&observations).unwrap();
+        assert_abs_diff_eq!(fit.rates()[0], 2.5, epsilon = 1e-9);
+    }
+
+    #[test]
+    fn test_noisy_constant() {
+        let model = Model::new(&HEIGHTS, 100.0).unwrap();
+        let mut observations = observe(&model, |time| 1.5 * time);
+        for (idx, observation) in observations.iter_mut().enumerate() {
+            let noise = if idx % 2 == 0 { 0.01 } else { -0.01 };
+            *observation = Observation::new(observation.time(), observation.column(), observation.level() + noise);
+        }
+
+        let fit = model.fit_constant_rain(&observations).unwrap();
+        assert_abs_diff_eq!(fit.rates()[0], 1.5, epsilon = 1e-2);
+        assert!(fit.sum_of_squares() > 0.0);
+        for residual in fit.residuals() {
+            assert!(residual.abs() < 0.1);
+        }
+    }
+
+    #[test]
+    fn test_piecewise() {
+        let model = Model::new(&HEIGHTS, 100.0).unwrap();
+        let observations = observe(&model, |time| {
+            if time < 1.0 {
+                2.0 * time
+            } else {
+                2.0 + 0.5 * (time - 1.0)
+            }
+        });
+
+        let fit = model.fit_piecewise_rain(&observations, &[1.0]).unwrap();
+        assert_eq!(fit.starts(), &[0.0, 1.0]);
+        assert_abs_diff_eq!(fit.rates()[0], 2.0, epsilon = 1e-6);
+        assert_abs_diff_eq!(fit.rates()[1], 0.5, epsilon = 1e-6);
+        assert_abs_diff_eq!(fit.rate_at(&0.5), 2.0, epsilon = 1e-6);
+        assert_abs_diff_eq!(fit.sum_of_squares(), 0.0, epsilon = 1e-9);
+    }
+
+    #[test]
+    fn test_horizon() {
+        let model = Model::new(&HEIGHTS, 2.0).unwrap();
+        let observations = vec![Observation::new(1.0, 1, 100.0)];
+
+        // the water can not rise higher than the horizon of the model permits
+        let fit = model.fit_constant_rain(&observations).unwrap();
+        assert!(fit.rates()[0] <= 2.0);
+        assert!(fit.residuals()[0] > 0.0);
+    }
+
+    #[test]
+    fn test_errors() {
+        let model = Model::new(&HEIGHTS, 100.0).unwrap();
+        let valid = Observation::new(1.0, 1, 2.0);
+
+        assert_eq!(model.fit_constant_rain(&[]), Err(ModelError::NoObservations));
+        assert_eq!(
+            model.fit_constant_rain(&[valid.clone(), Observation::new(1.0, 6, 2.0)]),
+            Err(ModelError::InvalidObservation { index: 1 }),
+        );
+        assert_eq!(
+            model.fit_constant_rain(&[Observation::new(-1.0, 0, 2.0)]),
+            Err(ModelError::InvalidObservation { index: 0 }),
+        );
+        assert_eq!(
+            model.fit_constant_rain(&[Observation::new(1.0, 0, f64::NAN)]),
+            Err(ModelError::InvalidObservation { index: 0 }),
+        );
+        assert_eq!(model.fit_piecewise_rain(slice::from_ref(&valid), &[0.0]), Err(ModelError::InvalidBreakpoints));
+        assert_eq!(model.fit_piecewise_rain(slice::from_ref(&valid), &[2.0, 1.0]), Err(ModelError::InvalidBreakpoints));
+
+        let dry = ModelBuilder::new().heights(&HEIGHTS).max_time(100.0).rain_rate(0.0).build().unwrap();
+        assert_eq!(dry.fit_constant_rain(&[valid]), Err(ModelError::InvalidRainRate));
+    }
+}
@@ -0,0 +1,143 @@
+use alloc::vec;
+use alloc::vec::Vec;
+use core::iter;
+use core::ops::Range;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::error::ModelError;
+use crate::model::Model;
+use crate::scalar::Scalar;
+
+/// Basins receiving the rain falling on a column
+///
+/// Basins are referred by their index in `Catchments::basins`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Catchment {
+    /// All the rain ends up in a single basin
+    Basin(usize),
+
+    /// Column is a peak, rain is split equally between the left and the right basins
+    Split(usize, usize),
+
+    /// Rain does not reach any basin
+    None,
+}
+
+/// Assignment of the columns to the basins, where their rain ends up
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Catchments<T = f64> {
+    basins: Vec<Range<usize>>,
+    labels: Vec<Catchment>,
+    sizes: Vec<T>,
+}
+
+impl<T: Scalar> Catchments<T> {
+    /// Columns of the basins, i.e. parts retaining all the water reaching them
+    pub fn basins(&self) -> &[Range<usize>] {
+        &self.basins
+    }
+
+    /// Catchment label of each column
+    pub fn labels(&self) -> &[Catchment] {
+        &self.labels
+    }
+
+    /// Number of columns draining into each basin, split columns count as a half
+    ///
+    /// Multiplied by the rain rate gives the amount of water the basin receives
+    pub fn sizes(&self) -> &[T] {
+        &self.sizes
+    }
+}
+
+impl<T: Scalar> Model<T> {
+    /// Find the basin where the rain falling on each column ends up at the provided time
+    pub fn catchments_at(&self, time: T) -> Result<Catchments<T>, ModelError> {
+        let (_, parts) = self.generation_at(time)?;
+        let parts_slice = parts.as_ref();
+
+        // index of the basin for each part accepting water
+        let mut basin_of_part = vec![None; parts_slice.len()];
+        let mut basins = Vec::new();
+        for (idx, part) in parts_slice.iter().enumerate() {
+            if parts.accepts_water(idx) {
+                basin_of_part[idx] = Some(basins.len());
+                basins.push(part.range());
+            }
+        }
+
+        let basin = |idx: Option<usize>| idx.and_then(|idx| basin_of_part.get(idx).copied().flatten());
+
+        let mut sizes = vec![T::zero(); basins.len()];
+        let mut labels = Vec::with_capacity(parts.num_columns());
+        for (idx, part) in parts_slice.iter().enumerate() {
+            let (left, right) = parts.runoff_destinations(idx);
+
+            let label = match (basin(Some(idx)), basin(left), basin(right)) {
+                (Some(own), _, _) => Catchment::Basin(own),
+                (None, Some(left), Some(right)) => Catchment::Split(left, right),
+                (None, Some(single), None) | (None, None, Some(single)) => Catchment::Basin(single),
+                (None, None, None) => Catchment::None,
+            };
+
+            let amount = T::from_usize(part.range().len());
+            match label {
+                Catchment::Basin(basin) => {
+                    sizes[basin] = sizes[basin].clone() + amount;
+                }
+                Catchment::Split(left, right) => {
+                    let half = amount / T::from_usize(2);
+                    sizes[left] = sizes[left].clone() + half.clone();
+                    sizes[right] = sizes[right].clone() + half;
+                }
+                Catchment::None => {}
+            }
+
+            labels.extend(iter::repeat_n(label, part.range().len()));
+        }
+
+        Ok(Catchments {
+            basins,
+            labels,
+            sizes,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_catchments() {
+        let model = Model::new(&[3.0, 1.0, 6.0, 4.0, 8.0, 9.0], 20.0).unwrap();
+
+        let catchments = model.catchments_at(0.0).unwrap();
+        assert_eq!(catchments.basins(), &[1..2, 3..4]);
+        assert_eq!(catchments.labels(), &[
+            Catchment::Basin(0),
+            Catchment::Basin(0),
+            Catchment::Split(0, 1),
+            Catchment::Basin(1),
+            Catchment::Basin(1),
+            Catchment::Basin(1),
+        ]);
+        assert_eq!(catchments.sizes(), &[2.5, 3.5]);
+
+        // after the right basin is full, all the rain ends up in the left one
+        let catchments = model.catchments_at(0.6).unwrap();
+        assert_eq!(catchments.basins().len(), 1);
+        assert_eq!(catchments.basins()[0], 1..2);
+        assert!(catchments.labels().iter().all(|label| *label == Catchment::Basin(0)));
+        assert_eq!(catchments.sizes(), &[6.0]);
+
+        let catchments = model.catchments_at(20.0).unwrap();
+        assert_eq!(catchments.basins().len(), 1);
+        assert_eq!(catchments.basins()[0], 0..6);
+        assert_eq!(catchments.sizes(), &[6.0]);
+    }
+}
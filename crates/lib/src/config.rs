@@ -0,0 +1,29 @@
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::scalar::Scalar;
+use crate::tolerance::Tolerance;
+
+/// Options of the simulation, validated by `ModelBuilder`
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub(crate) struct Config<T> {
+    /// Policy to consider two heights equal
+    pub(crate) tolerance: Tolerance<T>,
+
+    /// Policy to consider two event times equal
+    pub(crate) time_tolerance: Tolerance<T>,
+
+    /// Amount of water falling on each column per unit of time
+    pub(crate) rain_rate: T,
+}
+
+impl<T: Scalar> Default for Config<T> {
+    fn default() -> Self {
+        Config {
+            tolerance: Tolerance::default(),
+            time_tolerance: Tolerance::default(),
+            rain_rate: T::from_usize(1),
+        }
+    }
+}
@@ -0,0 +1,290 @@
+use std::num::NonZeroUsize;
+use std::thread;
+
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+use rand_distr::{Distribution, Normal};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::error::ModelError;
+use crate::model::Model;
+
+/// Options of the ensemble run
+#[derive(Debug, Clone)]
+pub struct EnsembleOptions {
+    members: usize,
+    seed: u64,
+    threads: Option<usize>,
+}
+
+impl EnsembleOptions {
+    pub fn new(members: usize, seed: u64) -> Self {
+        EnsembleOptions {
+            members,
+            seed,
+            threads: None,
+        }
+    }
+
+    /// Number of threads to spread the members over, available parallelism by default
+    ///
+    /// Results do not depend on the number of threads
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = Some(threads);
+        self
+    }
+}
+
+/// 5th, 50th and 95th percentiles of the value over the ensemble members
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Band<T = f64> {
+    p5: T,
+    p50: T,
+    p95: T,
+}
+
+impl<T: Clone> Band<T> {
+    pub fn p5(&self) -> T {
+        self.p5.clone()
+    }
+
+    pub fn p50(&self) -> T {
+        self.p50.clone()
+    }
+
+    pub fn p95(&self) -> T {
+        self.p95.clone()
+    }
+}
+
+/// Percentile bands of levels and flood times over the ensemble
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct EnsembleBands {
+    times: Vec<f64>,
+    levels: Vec<Vec<Band>>,
+    flood_times: Vec<Band<Option<f64>>>,
+}
+
+impl EnsembleBands {
+    /// Requested times
+    pub fn times(&self) -> &[f64] {
+        &self.times
+    }
+
+    /// Bands of the level of each column at each requested time, indexed as `[time][column]`
+    pub fn levels(&self) -> &[Vec<Band>] {
+        &self.levels
+    }
+
+    /// Bands of the time each column first gets wet, see `Model::arrival_times`
+    ///
+    /// None stands for the columns staying dry within the max time, so it is larger than any time
+    pub fn flood_times(&self) -> &[Band<Option<f64>>] {
+        &self.flood_times
+    }
+}
+
+/// Levels at the requested times and arrival times of a single member
+struct Member {
+    levels: Vec<Vec<f64>>,
+    arrivals: Vec<Option<f64>>,
+}
+
+/// Nearest rank percentiles of the sorted values
+fn band<T: Clone>(sorted: &[T]) -> Option<Band<T>> {
+    let percentile = |p: usize| {
+        let rank = (p * sorted.len()).div_ceil(100);
+        sorted.get(rank.saturating_sub(1)).cloned()
+    };
+
+    Some(Band {
+        p5: percentile(5)?,
+        p50: percentile(50)?,
+        p95: percentile(95)?,
+    })
+}
+
+impl Model<f64> {
+    /// Run the model over the terrains perturbed with the normal measurement error
+    ///
+    /// `uncertainty` is the standard deviation of the height of each column, perturbed heights
+    /// below zero are clamped to zero. Each member gets its own stream of the random generator
+    /// seeded with `seed`, so the results are reproducible. Other options are the same as for this model.
+    pub fn ensemble(&self, uncertainty: &[f64], times: &[f64], options: &EnsembleOptions) -> Result<EnsembleBands, ModelError> {
+        let ground = self.ground_heights();
+        if uncertainty.len() != ground.len() {
+            return Err(ModelError::ColumnCountMismatch { expected: ground.len(), actual: uncertainty.len() });
+        }
+
+        let noise = uncertainty.iter()
+            .enumerate()
+            .map(|(index, deviation)| {
+                // negative deviations are accepted by `Normal` as mirrored
+                if !deviation.is_finite() || *deviation < 0.0 {
+                    return Err(ModelError::InvalidUncertainty { index });
+                }
+                Normal::new(0.0, *deviation).map_err(|_| ModelError::InvalidUncertainty { index })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        for time in times {
+            self.generation_at(*time)?;
+        }
+
+        if options.members == 0 {
+            return Err(ModelError::NoMembers);
+        }
+
+        let run = |member: usize| -> Result<Member, ModelError> {
+            let mut rng = ChaCha8Rng::seed_from_u64(options.seed);
+            rng.set_stream(member as u64);
+
+            let heights: Vec<f64> = ground.iter()
+                .zip(noise.iter())
+                .map(|(height, noise)| (height + noise.sample(&mut rng)).max(0.0))
+                .collect();
+            let model = self.rebuild(&heights, *self.max_time())?;
+
+            Ok(Member {
+                levels: times.iter().map(|time| model.calculate_levels(*time)).collect::<Result<_, _>>()?,
+                arrivals: model.arrival_times(),
+            })
+        };
+
+        let threads = options.threads
+            .or_else(|| thread::available_parallelism().ok().map(NonZeroUsize::get))
+            .unwrap_or(1)
+            .clamp(1, options.members);
+        let chunk = options.members.div_ceil(threads);
+
+        let members: Vec<Member> = thread::scope(|scope| {
+            let handles: Vec<_> = (0..options.members)
+                .step_by(chunk)
+                .map(|start| {
+                    let run = &run;
+                    scope.spawn(move || (start..(start + chunk).min(options.members)).map(run).collect::<Result<Vec<_>, _>>())
+                })
+                .collect();
+
+            handles.into_iter()
+                .map(|handle| handle.join().unwrap_or(Err(ModelError::Inconsistency("ensemble member panicked"))))
+                .collect::<Result<Vec<_>, _>>()
+        })?
+        .into_iter()
+        .flatten()
+        .collect();
+
+        let mut levels = Vec::with_capacity(times.len());
+        for time_idx in 0..times.len() {
+            let mut row = Vec::with_capacity(ground.len());
+            for column in 0..ground.len() {
+                let mut values: Vec<f64> = members.iter().map(|member| member.levels[time_idx][column]).collect();
+                values.sort_by(f64::total_cmp);
+                row.push(band(&values).ok_or(ModelError::NoMembers)?);
+            }
+            levels.push(row);
+        }
+
+        let mut flood_times = Vec::with_capacity(ground.len());
+        for column in 0..ground.len() {
+            let mut values: Vec<Option<f64>> = members.iter().map(|member| member.arrivals[column]).collect();
+            // dry members go last
+            values.sort_by(|a, b| match (a, b) {
+                (Some(a), Some(b)) => a.total_cmp(b),
+                (a, b) => b.is_some().cmp(&a.is_some()),
+            });
+            flood_times.push(band(&values).ok_or(ModelError::NoMembers)?);
+        }
+
+        Ok(EnsembleBands {
+            times: times.to_vec(),
+            levels,
+            flood_times,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_abs_diff_eq;
+
+    use super::*;
+
+    const HEIGHTS: [f64; 6] = [3.0, 1.0, 6.0, 4.0, 8.0, 9.0];
+
+    #[test]
+    fn test_no_uncertainty() {
+        let model = Model::new(&HEIGHTS, 20.0).unwrap();
+        let bands = model.ensemble(&[0.0; 6], &[0.2, 1.0], &EnsembleOptions::new(10, 1)).unwrap();
+
+        assert_eq!(bands.times(), &[0.2, 1.0]);
+        for (time_idx, time) in bands.times().iter().enumerate() {
+            let levels = model.calculate_levels(*time).unwrap();
+            for (band, level) in bands.levels()[time_idx].iter().zip(levels) {
+                assert_eq!(band.p5(), level);
+                assert_eq!(band.p50(), level);
+                assert_eq!(band.p95(), level);
+            }
+        }
+
+        for (band, arrival) in bands.flood_times().iter().zip(model.arrival_times()) {
+            assert_eq!(band.p50(), arrival);
+        }
+    }
+
+    #[test]
+    fn test_reproducible() {
+        let model = Model::new(&HEIGHTS, 20.0).unwrap();
+        let uncertainty = [0.1, 0.2, 0.3, 0.1, 0.5, 0.0];
+
+        let single = model.ensemble(&uncertainty, &[0.5, 2.0], &EnsembleOptions::new(50, 7).threads(1)).unwrap();
+        let parallel = model.ensemble(&uncertainty, &[0.5, 2.0], &EnsembleOptions::new(50, 7).threads(4)).unwrap();
+        assert_eq!(single, parallel);
+
+        let other = model.ensemble(&uncertainty, &[0.5, 2.0], &EnsembleOptions::new(50, 8).threads(4)).unwrap();
+        assert_ne!(single, other);
+
+        for row in single.levels() {
+            for band in row {
+                assert!(band.p5() <= band.p50());
+                assert!(band.p50() <= band.p95());
+            }
+        }
+
+        // the level of the left basin spreads along with the heights around it
+        let band = &single.levels()[0][1];
+        assert!(band.p5() < band.p95());
+        let expected = model.calculate_levels(0.5).unwrap()[1];
+        assert_abs_diff_eq!(band.p50(), expected, epsilon = 0.5);
+    }
+
+    #[test]
+    fn test_dry_members() {
+        let model = Model::new(&HEIGHTS, 1.0).unwrap();
+        let bands = model.ensemble(&[0.0; 6], &[], &EnsembleOptions::new(3, 1)).unwrap();
+
+        assert_eq!(bands.flood_times()[5].p5(), None);
+        assert_eq!(bands.flood_times()[1].p95(), Some(0.0));
+    }
+
+    #[test]
+    fn test_errors() {
+        let model = Model::new(&HEIGHTS, 1.0).unwrap();
+        let options = EnsembleOptions::new(3, 1);
+
+        assert_eq!(
+            model.ensemble(&[0.0; 2], &[], &options),
+            Err(ModelError::ColumnCountMismatch { expected: 6, actual: 2 }),
+        );
+        assert_eq!(
+            model.ensemble(&[0.0, 0.0, -1.0, 0.0, 0.0, 0.0], &[], &options),
+            Err(ModelError::InvalidUncertainty { index: 2 }),
+        );
+        assert_eq!(model.ensemble(&[0.0; 6], &[2.0], &options), Err(ModelError::TimeOutOfHorizon));
+        assert_eq!(model.ensemble(&[0.0; 6], &[], &EnsembleOptions::new(0, 1)), Err(ModelError::NoMembers));
+    }
+}
@@ -0,0 +1,395 @@
+use alloc::vec;
+use alloc::vec::Vec;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::error::ModelError;
+use crate::model::Model;
+use crate::scalar::Scalar;
+use crate::tolerance::Tolerance;
+
+/// Water released from a lake, when the spillway it overflows gets lower
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Release<T = f64> {
+    time: T,
+    spillway: usize,
+    volume: T,
+}
+
+impl<T: Scalar> Release<T> {
+    pub fn time(&self) -> T {
+        self.time.clone()
+    }
+
+    /// Eroded column the water runs over
+    pub fn spillway(&self) -> usize {
+        self.spillway
+    }
+
+    pub fn volume(&self) -> T {
+        self.volume.clone()
+    }
+}
+
+/// Period of the erosion run with the fixed ground
+#[derive(Debug)]
+struct ErosionStep<T> {
+    start: T,
+    ground: Vec<T>,
+
+    /// model over the water surface at the start of the step
+    model: Model<T>,
+}
+
+/// Simulation of the model over the ground eroded by the water spilling over the rims
+#[derive(Debug)]
+pub struct ErosionRun<T = f64> {
+    steps: Vec<ErosionStep<T>>,
+    releases: Vec<Release<T>>,
+    eroded_ground: Vec<T>,
+}
+
+impl<T: Scalar> ErosionRun<T> {
+    fn step_at(&self, time: &T) -> Result<&ErosionStep<T>, ModelError> {
+        let idx = self.steps.partition_point(|step| step.start <= *time).saturating_sub(1);
+        self.steps.get(idx).ok_or(ModelError::Inconsistency("no erosion steps"))
+    }
+
+    /// Levels of the water at the time, see `Model::calculate_levels`
+    pub fn levels_at(&self, time: T) -> Result<Vec<T>, ModelError> {
+        let step = self.step_at(&time)?;
+        step.model.calculate_levels(time - step.start.clone())
+    }
+
+    /// Heights of the ground at the time
+    pub fn ground_at(&self, time: T) -> Result<Vec<T>, ModelError> {
+        let step = self.step_at(&time)?;
+        step.model.generation_at(time - step.start.clone())?;
+        Ok(step.ground.clone())
+    }
+
+    /// Heights of the ground at the max time of the model
+    pub fn eroded_ground(&self) -> &[T] {
+        &self.eroded_ground
+    }
+
+    /// Releases of water ordered by time
+    pub fn releases(&self) -> &[Release<T>] {
+        &self.releases
+    }
+
+    /// Times, when neighbouring parts merge, ordered
+    pub fn merge_times(&self) -> Vec<T> {
+        self.steps.iter()
+            .flat_map(|step| {
+                let duration = step.model.max_time();
+                step.model.generations()
+                    .iter()
+                    .skip(1)
+                    .filter(move |generation| generation.start <= *duration)
+                    .map(move |generation| step.start.clone() + generation.start.clone())
+            })
+            .collect()
+    }
+}
+
+/// Volume of water running over each dry rim of a spilling lake within the duration
+fn spillway_flows<T: Scalar>(model: &Model<T>, ground: &[T], duration: &T, tolerance: &Tolerance<T>) -> Vec<T> {
+    let rain_rate = &model.config().rain_rate;
+    let mut passed = vec![T::zero(); ground.len()];
+
+    for (idx, generation) in model.generations().iter().enumerate() {
+        if idx > 0 && generation.start >= *duration {
+            break;
+        }
+        let end = generation.end.clone().filter(|end| end < duration).unwrap_or_else(|| duration.clone());
+        let span = end - generation.start.clone();
+
+        // water leaving each part over its left and right edges per unit of time,
+        // the rain on a part runs through all the parts down to its destination
+        let parts = &generation.parts;
+        let inner = parts.as_ref();
+        let mut outflows = vec![(T::zero(), T::zero()); inner.len()];
+        for (idx, part) in inner.iter().enumerate() {
+            let amount = rain_rate.clone() * T::from_usize(part.range().len());
+            let (left, right) = parts.runoff_destinations(idx);
+            let share = if left.is_some() && right.is_some() {
+                amount / T::from_usize(2)
+            } else {
+                amount
+            };
+
+            if let Some(left) = left {
+                for outflow in &mut outflows[left + 1..=idx] {
+                    outflow.0 = outflow.0.clone() + share.clone();
+                }
+            }
+            if let Some(right) = right {
+                for outflow in &mut outflows[idx..right] {
+                    outflow.1 = outflow.1.clone() + share.clone();
+                }
+            }
+        }
+
+        for (part, (left, right)) in inner.iter().zip(outflows) {
+            let height = part.height();
+            let range = part.range();
+            if range.clone().all(|column| tolerance.is_equal(&height, &ground[column])) {
+                continue;
+            }
+
+            for (edge, flow) in [(range.start, left), (range.end - 1, right)] {
+                if flow > T::zero() && tolerance.is_equal(&height, &ground[edge]) {
+                    passed[edge] = passed[edge].clone() + flow * span.clone();
+                }
+            }
+        }
+    }
+
+    passed
+}
+
+/// Pour the volume of water onto the surface at the column, it runs down and fills the basins
+fn pour<T: Scalar>(surface: &mut [T], column: usize, volume: T, tolerance: &Tolerance<T>) {
+    let mut pending = vec![(column, volume)];
+
+    while let Some((column, mut volume)) = pending.pop() {
+        let mut column = column;
+        loop {
+            let level = surface[column].clone();
+            let mut start = column;
+            while start > 0 && tolerance.is_equal(&surface[start - 1], &level) {
+                start -= 1;
+            }
+            let mut end = column + 1;
+            while end < surface.len() && tolerance.is_equal(&surface[end], &level) {
+                end += 1;
+            }
+
+            let left = start.checked_sub(1).map(|idx| surface[idx].clone());
+            let right = surface.get(end).cloned();
+            let left_lower = left.as_ref().is_some_and(|left| *left < level);
+            let right_lower = right.as_ref().is_some_and(|right| *right < level);
+
+            // same split as for the rain running off a flat part
+            match (left_lower, right_lower) {
+                (true, true) => {
+                    let half = volume / T::from_usize(2);
+                    pending.push((end, half.clone()));
+                    volume = half;
+                    column = start - 1;
+                    continue;
+                }
+                (true, false) => {
+                    column = start - 1;
+                    continue;
+                }
+                (false, true) => {
+                    column = end;
+                    continue;
+                }
+                (false, false) => {}
+            }
+
+            let width = T::from_usize(end - start);
+            let lowest = match (left, right) {
+                (Some(left), Some(right)) => Some(if left < right { left } else { right }),
+                (Some(single), None) | (None, Some(single)) => Some(single),
+                (None, None) => None,
+            };
+
+            let needed = lowest.as_ref().map(|lowest| (lowest.clone() - level.clone()) * width.clone());
+            match (lowest, needed) {
+                (Some(lowest), Some(needed)) if needed < volume => {
+                    for height in &mut surface[start..end] {
+                        *height = lowest.clone();
+                    }
+                    volume = volume - needed;
+                }
+                _ => {
+                    let raised = level + volume / width;
+                    for height in &mut surface[start..end] {
+                        *height = raised.clone();
+                    }
+                    break;
+                }
+            }
+        }
+    }
+}
+
+impl<T: Scalar> Model<T> {
+    /// Simulate the model with the rims of the spilling lakes eroded by the water running over them
+    ///
+    /// A dry column at the edge of a spilling lake, over which the water leaves it, loses
+    /// `erodibility` of height per unit of volume passed, but never goes below zero.
+    /// The ground is fixed within each step and eroded at its end, then the lake drains down
+    /// to the lowered rim and the released water runs down to the basins below. A rim covered
+    /// by the rising water by the end of a step is not eroded within that step.
+    /// Generations are recalculated for each step over the water surface, so the merges
+    /// are reported at their exact times, while the erosion lags by up to a step.
+    pub fn erode(&self, erodibility: T, step: T) -> Result<ErosionRun<T>, ModelError> {
+        if !erodibility.is_finite() || erodibility.is_negative() || !step.is_finite() || step <= T::zero() {
+            return Err(ModelError::InvalidErosionParameters);
+        }
+
+        let tolerance = &self.config().tolerance;
+        let mut ground = self.ground_heights();
+        let mut surface = ground.clone();
+        let mut start = T::zero();
+        let mut steps = Vec::new();
+        let mut releases = Vec::new();
+
+        loop {
+            let remaining = self.max_time().clone() - start.clone();
+            let duration = if step < remaining { step.clone() } else { remaining };
+            let model = self.rebuild(&surface, duration.clone())?;
+
+            let passed = spillway_flows(&model, &ground, &duration, tolerance);
+            let mut next = model.calculate_levels(duration.clone())?;
+            let end = start.clone() + duration;
+
+            let mut eroded = ground.clone();
+            for (spillway, volume) in passed.into_iter().enumerate() {
+                // the rim covered by the rising water within the step is not a spillway anymore
+                if volume <= T::zero() || !tolerance.is_equal(&next[spillway], &ground[spillway]) {
+                    continue;
+                }
+
+                let lowered = ground[spillway].clone() - erodibility.clone() * volume;
+                eroded[spillway] = if lowered.is_negative() { T::zero() } else { lowered };
+                if eroded[spillway] >= ground[spillway] {
+                    continue;
+                }
+
+                // the lake around the rim drains down to it
+                let level = next[spillway].clone();
+                let rim = eroded[spillway].clone();
+                next[spillway] = rim.clone();
+
+                let lake = (0..spillway).rev()
+                    .take_while(|column| tolerance.is_equal(&next[*column], &level))
+                    .chain((spillway + 1..next.len()).take_while(|column| tolerance.is_equal(&next[*column], &level)))
+                    .collect::<Vec<_>>();
+                let mut released = T::zero();
+                for column in lake {
+                    let drained = if eroded[column] > rim { eroded[column].clone() } else { rim.clone() };
+                    if drained < next[column] {
+                        released = released + next[column].clone() - drained.clone();
+                        next[column] = drained;
+                    }
+                }
+
+                if released > T::zero() {
+                    pour(&mut next, spillway, released.clone(), tolerance);
+                    releases.push(Release {
+                        time: end.clone(),
+                        spillway,
+                        volume: released,
+                    });
+                }
+            }
+
+            steps.push(ErosionStep {
+                start,
+                ground,
+                model,
+            });
+            ground = eroded;
+            surface = next;
+            start = end;
+
+            if start >= *self.max_time() {
+                break;
+            }
+        }
+
+        Ok(ErosionRun {
+            steps,
+            releases,
+            eroded_ground: ground,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_abs_diff_eq;
+
+    use super::*;
+
+    const HEIGHTS: [f64; 6] = [3.0, 1.0, 6.0, 4.0, 8.0, 9.0];
+
+    #[test]
+    fn test_no_erosion() {
+        let model = Model::new(&HEIGHTS, 3.0).unwrap();
+        let run = model.erode(0.0, 0.1).unwrap();
+
+        for time in [0.0, 0.3, 0.6, 1.0, 2.05, 3.0] {
+            let expected = model.calculate_levels(time).unwrap();
+            for (level, expected) in run.levels_at(time).unwrap().into_iter().zip(expected) {
+                assert_abs_diff_eq!(level, expected, epsilon = 1e-9);
+            }
+        }
+
+        assert!(run.releases().is_empty());
+        assert_eq!(run.eroded_ground(), &HEIGHTS);
+
+        // the right lake spills at 4 / 7, the left one covers the slope at 3.0 at 2 / 3
+        let merges = run.merge_times();
+        assert!(merges.iter().any(|time| (time - 4.0 / 7.0).abs() < 1e-9));
+        assert!(merges.iter().any(|time| (time - 2.0 / 3.0).abs() < 1e-9));
+    }
+
+    #[test]
+    fn test_spillway_erosion() {
+        let model = Model::new(&HEIGHTS, 1.0).unwrap();
+        let run = model.erode(0.25, 0.01).unwrap();
+
+        // only the wall at 6.0 carries the water of the right lake and field
+        let ground = run.eroded_ground();
+        assert!(ground[2] < 6.0);
+        for column in [0, 1, 3, 4, 5] {
+            assert_eq!(ground[column], HEIGHTS[column]);
+        }
+        assert_eq!(run.ground_at(0.5).unwrap(), HEIGHTS.to_vec());
+
+        assert!(!run.releases().is_empty());
+        assert!(run.releases().iter().all(|release| release.spillway() == 2 && release.time() > 4.0 / 7.0));
+
+        // the released water makes the left lake rise faster
+        let eroded = run.levels_at(0.65).unwrap();
+        let fixed = model.calculate_levels(0.65).unwrap();
+        assert!(eroded[1] > fixed[1]);
+        assert!(eroded[3] < fixed[3]);
+    }
+
+    #[test]
+    fn test_conservation() {
+        let model = Model::new(&HEIGHTS, 2.0).unwrap();
+        let run = model.erode(0.5, 0.02).unwrap();
+
+        for time in [0.3, 0.6, 0.75, 1.0, 1.5, 2.0] {
+            let levels = run.levels_at(time).unwrap();
+            let ground = run.ground_at(time).unwrap();
+            let volume: f64 = levels.iter().zip(&ground).map(|(level, height)| level - height).sum();
+            assert_abs_diff_eq!(volume, 6.0 * time, epsilon = 1e-9);
+            assert!(levels.iter().zip(&ground).all(|(level, height)| level >= height));
+        }
+    }
+
+    #[test]
+    fn test_errors() {
+        let model = Model::new(&HEIGHTS, 1.0).unwrap();
+
+        assert!(matches!(model.erode(-1.0, 0.1), Err(ModelError::InvalidErosionParameters)));
+        assert!(matches!(model.erode(1.0, 0.0), Err(ModelError::InvalidErosionParameters)));
+        assert!(matches!(model.erode(f64::NAN, 0.1), Err(ModelError::InvalidErosionParameters)));
+
+        let run = model.erode(1.0, 0.1).unwrap();
+        assert_eq!(run.levels_at(1.5), Err(ModelError::TimeOutOfHorizon));
+        assert_eq!(run.ground_at(-1.0), Err(ModelError::NegativeTime));
+    }
+}
@@ -0,0 +1,129 @@
+use core::fmt;
+use core::ops::Range;
+
+use crate::Index;
+
+/// Errors returned by the model
+///
+/// New variants may be added along with new analyses, so matches should have a wildcard arm
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ModelError {
+    /// No heights provided
+    EmptyInput,
+
+    /// Height at the index is negative, infinite or NaN
+    InvalidHeight { index: Index },
+
+    /// Max time was not provided
+    MissingMaxTime,
+
+    /// Max time is negative, infinite or NaN
+    InvalidMaxTime,
+
+    /// Merge or time tolerance is negative, infinite or NaN
+    InvalidTolerance,
+
+    /// Rain rate is negative, infinite or NaN
+    InvalidRainRate,
+
+    /// Requested time is NaN or infinite
+    InvalidTime,
+
+    /// Requested time is negative
+    NegativeTime,
+
+    /// Requested time is beyond the max time of the model
+    TimeOutOfHorizon,
+
+    /// Requested columns are outside of the terrain
+    RangeOutOfBounds { range: Range<Index>, num_columns: usize },
+
+    /// No observations provided to fit the model to
+    NoObservations,
+
+    /// Observation at the index has a negative or NaN time, unknown column or NaN level
+    InvalidObservation { index: Index },
+
+    /// Breakpoints of the rain schedule are not positive and strictly increasing
+    InvalidBreakpoints,
+
+    /// Number of the provided per-column values differs from the number of columns
+    ColumnCountMismatch { expected: usize, actual: usize },
+
+    /// Threshold at the index is infinite or NaN, or a negative depth
+    InvalidThreshold { index: Index },
+
+    /// No raise of the candidate columns keeps the protected columns dry until the deadline
+    Infeasible,
+
+    /// Uncertainty at the index is negative, infinite or NaN
+    InvalidUncertainty { index: Index },
+
+    /// Ensemble should have at least one member
+    NoMembers,
+
+    /// Storm parameters or horizon are negative, infinite or NaN
+    InvalidStormParameters,
+
+    /// Erodibility is negative, or erosion step is not positive, infinite or NaN
+    InvalidErosionParameters,
+
+    /// Step of the reference simulation is not positive, infinite or NaN
+    InvalidStep,
+
+    /// Levels are not differentiable at the time, because several merges coincide before it
+    NonDifferentiable,
+
+    /// Internal state of the simulation is inconsistent
+    Inconsistency(&'static str),
+
+    /// Deserialized model failed validation
+    Corrupted(&'static str),
+}
+
+impl fmt::Display for ModelError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ModelError::EmptyInput => write!(f, "should not be empty"),
+            ModelError::InvalidHeight { index } => {
+                write!(f, "height at index {} should be a positive number", index)
+            }
+            ModelError::MissingMaxTime => write!(f, "max time should be provided"),
+            ModelError::InvalidMaxTime => write!(f, "max time should be a positive number"),
+            ModelError::InvalidTolerance => write!(f, "merge and time tolerances should be positive numbers"),
+            ModelError::InvalidRainRate => write!(f, "rain rate should be a positive number"),
+            ModelError::InvalidTime => write!(f, "time should be a number"),
+            ModelError::NegativeTime => write!(f, "time should not be negative"),
+            ModelError::TimeOutOfHorizon => write!(f, "more than max time provided"),
+            ModelError::RangeOutOfBounds { range, num_columns } => {
+                write!(f, "range {:?} is out of bounds of {} columns", range, num_columns)
+            }
+            ModelError::NoObservations => write!(f, "at least one observation should be provided"),
+            ModelError::InvalidObservation { index } => {
+                write!(f, "observation at index {} should have a valid time, column and level", index)
+            }
+            ModelError::InvalidBreakpoints => write!(f, "breakpoints should be positive and strictly increasing"),
+            ModelError::ColumnCountMismatch { expected, actual } => {
+                write!(f, "expected values for {} columns, got {}", expected, actual)
+            }
+            ModelError::InvalidThreshold { index } => write!(f, "threshold at index {} should be a number, depths should not be negative", index),
+            ModelError::Infeasible => write!(f, "protected columns can not be kept dry by the candidates"),
+            ModelError::InvalidUncertainty { index } => {
+                write!(f, "uncertainty at index {} should be a positive number", index)
+            }
+            ModelError::NoMembers => write!(f, "ensemble should have at least one member"),
+            ModelError::InvalidStormParameters => write!(f, "storm parameters should be positive numbers"),
+            ModelError::InvalidErosionParameters => {
+                write!(f, "erodibility should be a non-negative number and erosion step a positive one")
+            }
+            ModelError::InvalidStep => write!(f, "step of the reference simulation should be a positive number"),
+            ModelError::NonDifferentiable => write!(f, "levels are not differentiable, as several merges coincide"),
+            ModelError::Inconsistency(reason) => write!(f, "internal inconsistency: {}", reason),
+            ModelError::Corrupted(reason) => write!(f, "corrupted model: {}", reason),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for ModelError {}
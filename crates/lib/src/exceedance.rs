@@ -0,0 +1,154 @@
+use alloc::vec::Vec;
+use core::cmp::Ordering;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::error::ModelError;
+use crate::model::Model;
+use crate::scalar::Scalar;
+
+/// Critical water at a column
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Threshold<T = f64> {
+    /// Depth of the water above the ground
+    Depth(T),
+
+    /// Absolute level of the water
+    Level(T),
+}
+
+/// Times at which the columns first reach their thresholds
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Exceedance<T = f64> {
+    times: Vec<Option<T>>,
+    order: Vec<usize>,
+}
+
+impl<T: Scalar> Exceedance<T> {
+    /// Time of the first crossing for each column, None if the threshold
+    /// is not reached within the max time of the model
+    pub fn times(&self) -> &[Option<T>] {
+        &self.times
+    }
+
+    /// Columns reaching their thresholds, ordered by the time of crossing,
+    /// columns crossing at the same time are ordered by their index
+    pub fn order(&self) -> &[usize] {
+        &self.order
+    }
+}
+
+impl<T: Scalar> Model<T> {
+    /// Find the exact time each column first reaches its threshold
+    ///
+    /// Levels only rise, so a threshold once reached stays exceeded.
+    /// Thresholds already met before the rain have the time of zero.
+    /// Depths should be non-negative.
+    pub fn first_exceedance(&self, thresholds: &[Threshold<T>]) -> Result<Exceedance<T>, ModelError> {
+        let ground = self.ground_heights();
+        if thresholds.len() != ground.len() {
+            return Err(ModelError::ColumnCountMismatch { expected: ground.len(), actual: thresholds.len() });
+        }
+
+        let mut times = Vec::with_capacity(ground.len());
+        for (column, (threshold, ground)) in thresholds.iter().zip(ground).enumerate() {
+            let target = match threshold {
+                Threshold::Depth(depth) if depth.is_finite() && !depth.is_negative() => ground + depth.clone(),
+                Threshold::Level(level) if level.is_finite() => level.clone(),
+                _ => return Err(ModelError::InvalidThreshold { index: column }),
+            };
+
+            let time = self.column_segments(column)
+                .take_while(|segment| segment.start <= *self.max_time())
+                .find_map(|segment| segment.time_of_level(&target))
+                .filter(|time| *time <= *self.max_time());
+            times.push(time);
+        }
+
+        let mut order: Vec<usize> = (0..times.len()).filter(|column| times[*column].is_some()).collect();
+        order.sort_by(|a, b| times[*a].partial_cmp(&times[*b]).unwrap_or(Ordering::Equal));
+
+        Ok(Exceedance { times, order })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_abs_diff_eq;
+
+    use super::*;
+
+    #[test]
+    fn test_depth() {
+        let model = Model::new(&[3.0, 1.0, 6.0, 4.0, 8.0, 9.0], 2.0).unwrap();
+        let thresholds = [
+            Threshold::Depth(0.5),
+            Threshold::Depth(1.0),
+            Threshold::Depth(0.0),
+            Threshold::Depth(1.0),
+            Threshold::Depth(0.5),
+            Threshold::Depth(0.5),
+        ];
+
+        let exceedance = model.first_exceedance(&thresholds).unwrap();
+        let times = exceedance.times();
+
+        // the left basin gets the rain on the slope of 3.0 and half of the rain on the peak at 6.0
+        assert_abs_diff_eq!(times[1].unwrap(), 1.0 / 2.5);
+        // the right basin collects the rain from the slope of 8.0 and 9.0
+        assert_abs_diff_eq!(times[3].unwrap(), 1.0 / 3.5);
+        // zero depth is reached right away
+        assert_eq!(times[2], Some(0.0));
+        // the peaks are covered only after the whole terrain fills up
+        assert_eq!(times[4], None);
+        assert_eq!(times[5], None);
+
+        assert_eq!(exceedance.order()[0], 2);
+        assert_eq!(exceedance.order()[1], 3);
+        assert_eq!(exceedance.order()[2], 1);
+        assert_eq!(exceedance.order().len(), 4);
+    }
+
+    #[test]
+    fn test_level_matches_levels() {
+        let model = Model::new(&[3.0, 1.0, 6.0, 4.0, 8.0, 9.0], 20.0).unwrap();
+        let thresholds: Vec<_> = [5.0, 5.0, 7.0, 7.0, 8.5, 9.5].iter().map(|level| Threshold::Level(*level)).collect();
+        let exceedance = model.first_exceedance(&thresholds).unwrap();
+
+        for (column, time) in exceedance.times().iter().enumerate() {
+            let time: f64 = time.unwrap();
+            let level = match &thresholds[column] {
+                Threshold::Level(level) => *level,
+                _ => unreachable!(),
+            };
+
+            assert_abs_diff_eq!(model.calculate_levels(time).unwrap()[column], level, epsilon = 1e-9);
+            assert!(model.calculate_levels((time - 1e-6).max(0.0)).unwrap()[column] < level);
+        }
+    }
+
+    #[test]
+    fn test_errors() {
+        let model = Model::new(&[3.0, 1.0, 6.0], 2.0).unwrap();
+
+        assert_eq!(
+            model.first_exceedance(&[Threshold::Depth(1.0)]),
+            Err(ModelError::ColumnCountMismatch { expected: 3, actual: 1 }),
+        );
+        assert_eq!(
+            model.first_exceedance(&[Threshold::Depth(1.0), Threshold::Level(f64::NAN), Threshold::Depth(1.0)]),
+            Err(ModelError::InvalidThreshold { index: 1 }),
+        );
+        assert_eq!(
+            model.first_exceedance(&[Threshold::Depth(1.0), Threshold::Depth(1.0), Threshold::Depth(-0.5)]),
+            Err(ModelError::InvalidThreshold { index: 2 }),
+        );
+        assert_eq!(
+            model.first_exceedance(&[Threshold::Depth(f64::INFINITY), Threshold::Depth(1.0), Threshold::Depth(1.0)]),
+            Err(ModelError::InvalidThreshold { index: 0 }),
+        );
+    }
+}
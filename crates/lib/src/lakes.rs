@@ -0,0 +1,164 @@
+use alloc::vec::Vec;
+use core::ops::Range;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::error::ModelError;
+use crate::model::Model;
+use crate::scalar::Scalar;
+
+/// What happens to the water reaching the lake
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum LakeState {
+    /// Lake retains all the water and its level rises
+    Filling,
+
+    /// Lake is full and the water runs over its rim into neighbouring lakes
+    ///
+    /// Contains columns of the lakes receiving the water on each side
+    Overflowing {
+        left: Option<Range<usize>>,
+        right: Option<Range<usize>>,
+    },
+}
+
+/// Body of standing water
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Lake<T = f64> {
+    range: Range<usize>,
+    level: T,
+    depth: T,
+    volume: T,
+    state: LakeState,
+}
+
+impl<T: Scalar> Lake<T> {
+    /// Columns covered by the lake
+    pub fn range(&self) -> Range<usize> {
+        self.range.clone()
+    }
+
+    /// Surface level of the water
+    pub fn level(&self) -> T {
+        self.level.clone()
+    }
+
+    /// Depth of the water at the deepest point
+    pub fn depth(&self) -> T {
+        self.depth.clone()
+    }
+
+    /// Amount of the stored water
+    pub fn volume(&self) -> T {
+        self.volume.clone()
+    }
+
+    pub fn state(&self) -> &LakeState {
+        &self.state
+    }
+}
+
+impl<T: Scalar> Model<T> {
+    /// Find all bodies of standing water at the provided time
+    ///
+    /// Lakes are ordered by their columns
+    pub fn lakes_at(&self, time: T) -> Result<Vec<Lake<T>>, ModelError> {
+        let (offset, parts) = self.generation_at(time)?;
+        let current = parts.calculate_parts_at_rel_time(&offset);
+
+        let mut lakes = Vec::new();
+        for (idx, part) in current.iter().enumerate() {
+            let level = part.height();
+            let mut depth = T::zero();
+            let mut volume = T::zero();
+
+            for ground in self.initial_parts().calculate_parts_in_range_at_rel_time(&T::zero(), part.range()) {
+                if ground.height() < level {
+                    let column_depth = level.clone() - ground.height();
+                    volume = volume + column_depth.clone() * T::from_usize(ground.range().len());
+                    if column_depth > depth {
+                        depth = column_depth;
+                    }
+                }
+            }
+
+            if volume <= T::zero() {
+                continue;
+            }
+
+            let state = if parts.accepts_water(idx) {
+                LakeState::Filling
+            } else {
+                let (left, right) = parts.runoff_destinations(idx);
+                LakeState::Overflowing {
+                    left: left.and_then(|left| current.get(left)).map(|part| part.range()),
+                    right: right.and_then(|right| current.get(right)).map(|part| part.range()),
+                }
+            };
+
+            lakes.push(Lake {
+                range: part.range(),
+                level,
+                depth,
+                volume,
+                state,
+            });
+        }
+
+        Ok(lakes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_abs_diff_eq;
+
+    use super::*;
+
+    #[test]
+    fn test_filling() {
+        let model = Model::new(&[3.0, 1.0, 6.0, 4.0, 8.0, 9.0], 20.0).unwrap();
+
+        assert!(model.lakes_at(0.0).unwrap().is_empty());
+
+        let lakes = model.lakes_at(0.2).unwrap();
+        assert_eq!(lakes.len(), 2);
+
+        assert_eq!(lakes[0].range(), 1..2);
+        assert_abs_diff_eq!(lakes[0].level(), 1.5);
+        assert_abs_diff_eq!(lakes[0].depth(), 0.5);
+        assert_abs_diff_eq!(lakes[0].volume(), 0.5);
+        assert_eq!(lakes[0].state(), &LakeState::Filling);
+
+        assert_eq!(lakes[1].range(), 3..4);
+        assert_abs_diff_eq!(lakes[1].level(), 4.7);
+        assert_abs_diff_eq!(lakes[1].depth(), 0.7);
+        assert_abs_diff_eq!(lakes[1].volume(), 0.7);
+        assert_eq!(lakes[1].state(), &LakeState::Filling);
+    }
+
+    #[test]
+    fn test_overflowing() {
+        let model = Model::new(&[3.0, 1.0, 6.0, 4.0, 8.0, 9.0], 20.0).unwrap();
+
+        // the right lake reaches the level of 6.0 at 2 / 3.5
+        let lakes = model.lakes_at(0.6).unwrap();
+        assert_eq!(lakes.len(), 2);
+
+        assert_eq!(lakes[1].range(), 2..4);
+        assert_abs_diff_eq!(lakes[1].level(), 6.0);
+        assert_abs_diff_eq!(lakes[1].depth(), 2.0);
+        assert_abs_diff_eq!(lakes[1].volume(), 2.0);
+        assert_eq!(lakes[1].state(), &LakeState::Overflowing { left: Some(1..2), right: None });
+
+        // all the water is in a single lake in the end
+        let lakes = model.lakes_at(20.0).unwrap();
+        assert_eq!(lakes.len(), 1);
+        assert_eq!(lakes[0].range(), 0..6);
+        assert_abs_diff_eq!(lakes[0].volume(), 20.0 * 6.0, epsilon = 1e-9);
+        assert_eq!(lakes[0].state(), &LakeState::Filling);
+    }
+}
@@ -1,11 +1,61 @@
+#![cfg_attr(all(not(feature = "std"), not(test)), no_std)]
+#![cfg_attr(not(test), deny(clippy::unwrap_used, clippy::expect_used, clippy::panic, clippy::unreachable))]
+
+extern crate alloc;
+
+pub use barrier::Barrier;
+pub use builder::ModelBuilder;
+pub use calibration::{Observation, RainFit};
+pub use catchment::{Catchment, Catchments};
+#[cfg(feature = "ensemble")]
+pub use ensemble::{Band, EnsembleBands, EnsembleOptions};
+pub use erosion::{ErosionRun, Release};
+pub use error::ModelError;
+pub use exceedance::{Exceedance, Threshold};
+pub use lakes::{Lake, LakeState};
+pub use merge_tree::{MergeNode, MergeTree};
 pub use model::Model;
 pub use parts::Part;
+pub use reference::{Deviation, Reference};
+pub use risk::FloodRisk;
+pub use scalar::Scalar;
+pub use sensitivity::{Derivatives, Sensitivity};
+pub use spills::Spill;
+#[cfg(feature = "storms")]
+pub use storms::{Storm, StormGenerator, StormRun, StormSequence, StormStatistics};
+pub use tolerance::Tolerance;
+pub use tracers::{TracedLake, Tracers};
 
+mod barrier;
+mod builder;
+mod calibration;
+mod catchment;
+mod config;
 mod parts;
 mod direction;
+#[cfg(feature = "ensemble")]
+mod ensemble;
+mod erosion;
+mod error;
+mod exceedance;
+mod lakes;
+mod merge_tree;
 mod model;
+mod reference;
+mod risk;
+mod scalar;
+mod segments;
+mod sensitivity;
+mod spills;
+#[cfg(feature = "storms")]
+mod storms;
+mod tolerance;
+mod tracers;
+mod trajectory;
+mod wetting;
+#[cfg(feature = "rational")]
+pub mod rational;
 
-type Height = f64;
 type Index = usize;
 
 #[cfg(test)]
@@ -15,6 +65,15 @@ mod tests {
 
     use super::*;
 
+    #[quickcheck]
+    fn never_panics(parts: Vec<f64>, times: Vec<f64>, max_time: f64) {
+        if let Ok(model) = Model::new(&parts, max_time) {
+            for time in times {
+                let _ = model.calculate_levels(time);
+            }
+        }
+    }
+
     #[quickcheck]
     fn invariant_always_met(parts: Vec<u32>, time: u32, max_time: u32) -> TestResult {
         if time > max_time {
@@ -43,7 +102,7 @@ mod tests {
                 TestResult::error(format!("{} - {} ({}) != {} * {} ({})", resulting_sum, initial_sum, calculated_amount_of_water, time, num_parts, expected_amount_of_water))
             }
         } else {
-            return TestResult::discard();
+            TestResult::discard()
         }
     }
 }
\ No newline at end of file
@@ -0,0 +1,329 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::{self, Write};
+use core::ops::Range;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::model::Model;
+use crate::scalar::Scalar;
+
+/// Part of the terrain, which exists between two merges
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct MergeNode<T = f64> {
+    range: Range<usize>,
+    formed_at: T,
+    level: T,
+    accepts_water: bool,
+    spill_time: Option<T>,
+    spill_level: Option<T>,
+    parent: Option<usize>,
+    children: Vec<usize>,
+}
+
+impl<T: Scalar> MergeNode<T> {
+    /// Columns covered by the node
+    pub fn range(&self) -> Range<usize> {
+        self.range.clone()
+    }
+
+    /// Time when the node was formed, zero for the initial parts
+    pub fn formed_at(&self) -> T {
+        self.formed_at.clone()
+    }
+
+    /// Level of the node when it was formed
+    pub fn level(&self) -> T {
+        self.level.clone()
+    }
+
+    /// Returns true for basins, false for ridges and lakes spilling over their rim
+    pub fn accepts_water(&self) -> bool {
+        self.accepts_water
+    }
+
+    /// Time when the node joined its parent, None for roots
+    pub fn spill_time(&self) -> Option<T> {
+        self.spill_time.clone()
+    }
+
+    /// Level at which the node joined its parent, None for roots
+    pub fn spill_level(&self) -> Option<T> {
+        self.spill_level.clone()
+    }
+
+    pub fn parent(&self) -> Option<usize> {
+        self.parent
+    }
+
+    pub fn children(&self) -> &[usize] {
+        &self.children
+    }
+}
+
+/// Fill-and-spill tree, in which basins fill, spill and join
+/// into larger ones until the terrain is flat
+///
+/// Leaves are the parts of the initial terrain, each merge of parts
+/// creates a new node with the merged parts as its children.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct MergeTree<T = f64> {
+    nodes: Vec<MergeNode<T>>,
+}
+
+impl<T: Scalar> MergeTree<T> {
+    /// All the nodes, children always precede their parents
+    pub fn nodes(&self) -> &[MergeNode<T>] {
+        &self.nodes
+    }
+
+    /// Nodes which never join any other node
+    pub fn roots(&self) -> impl Iterator<Item = usize> + '_ {
+        self.nodes.iter()
+            .enumerate()
+            .filter(|(_, node)| node.parent.is_none())
+            .map(|(idx, _)| idx)
+    }
+
+    /// Export the tree as JSON object with the list of nodes
+    ///
+    /// Available without the `serde` feature, values are written as the nearest `f64`.
+    /// Ids of the nodes are their indices in `nodes`.
+    pub fn to_json(&self) -> String {
+        let mut out = String::new();
+
+        out.push_str("{\"nodes\":[");
+        for (idx, node) in self.nodes.iter().enumerate() {
+            if idx > 0 {
+                out.push(',');
+            }
+
+            let _ = write!(
+                out,
+                "{{\"id\":{},\"columns\":[{},{}],\"formed_at\":{},\"level\":{},\"accepts_water\":{},",
+                idx,
+                node.range.start,
+                node.range.end,
+                JsonNumber(&node.formed_at),
+                JsonNumber(&node.level),
+                node.accepts_water,
+            );
+
+            match (&node.spill_time, &node.spill_level, node.parent) {
+                (Some(time), Some(level), Some(parent)) => {
+                    let _ = write!(out, "\"spill_time\":{},\"spill_level\":{},\"parent\":{},", JsonNumber(time), JsonNumber(level), parent);
+                }
+                _ => {
+                    out.push_str("\"spill_time\":null,\"spill_level\":null,\"parent\":null,");
+                }
+            }
+
+            out.push_str("\"children\":[");
+            for (child_idx, child) in node.children.iter().enumerate() {
+                if child_idx > 0 {
+                    out.push(',');
+                }
+                let _ = write!(out, "{}", child);
+            }
+            out.push_str("]}");
+        }
+        out.push_str("]}");
+
+        out
+    }
+
+    /// Export the tree as Graphviz DOT digraph
+    ///
+    /// Edges go from the merged node to its children and are labelled
+    /// with the spill time and level
+    pub fn to_dot(&self) -> String {
+        let mut out = String::new();
+
+        out.push_str("digraph merge_tree {\n");
+        for (idx, node) in self.nodes.iter().enumerate() {
+            let shape = if node.accepts_water { "ellipse" } else { "box" };
+            let _ = writeln!(
+                out,
+                "    n{} [shape={}, label=\"columns {}..{}\\nlevel {}\\nformed at {}\"];",
+                idx,
+                shape,
+                node.range.start,
+                node.range.end,
+                node.level.to_f64(),
+                node.formed_at.to_f64(),
+            );
+        }
+        for (idx, node) in self.nodes.iter().enumerate() {
+            if let (Some(parent), Some(time), Some(level)) = (node.parent, &node.spill_time, &node.spill_level) {
+                let _ = writeln!(
+                    out,
+                    "    n{} -> n{} [label=\"t={}\\nlevel {}\"];",
+                    parent,
+                    idx,
+                    time.to_f64(),
+                    level.to_f64(),
+                );
+            }
+        }
+        out.push_str("}\n");
+
+        out
+    }
+}
+
+/// Value written as JSON number, null for the values JSON cannot represent
+struct JsonNumber<'a, T>(&'a T);
+
+impl<T: Scalar> fmt::Display for JsonNumber<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let value = self.0.to_f64();
+        if value.is_finite() {
+            write!(f, "{}", value)
+        } else {
+            f.write_str("null")
+        }
+    }
+}
+
+impl<T: Scalar> Model<T> {
+    /// Build the tree of merges of the parts until the terrain is flat
+    ///
+    /// Includes the merges after the max time of the model
+    pub fn merge_tree(&self) -> MergeTree<T> {
+        let mut nodes: Vec<MergeNode<T>> = Vec::new();
+
+        // node of each part of the previous generation
+        let mut current: Vec<usize> = Vec::new();
+        let mut previous_ranges: Vec<Range<usize>> = Vec::new();
+
+        for generation in self.generations() {
+            let parts = generation.parts.as_ref();
+            let mut next = Vec::with_capacity(parts.len());
+            let mut previous_idx = 0;
+
+            for (idx, part) in parts.iter().enumerate() {
+                let range = part.range();
+
+                // previous parts covered by this one
+                let first = previous_idx;
+                while previous_idx < previous_ranges.len() && previous_ranges[previous_idx].end <= range.end {
+                    previous_idx += 1;
+                }
+                let merged = &current[first..previous_idx];
+
+                if let [single] = merged {
+                    if previous_ranges[first] == range {
+                        next.push(*single);
+                        continue;
+                    }
+                }
+
+                let node_idx = nodes.len();
+                for child in merged {
+                    let child = &mut nodes[*child];
+                    child.parent = Some(node_idx);
+                    child.spill_time = Some(generation.start.clone());
+                    child.spill_level = Some(part.height());
+                }
+
+                nodes.push(MergeNode {
+                    range,
+                    formed_at: generation.start.clone(),
+                    level: part.height(),
+                    accepts_water: generation.parts.accepts_water(idx),
+                    spill_time: None,
+                    spill_level: None,
+                    parent: None,
+                    children: merged.to_vec(),
+                });
+                next.push(node_idx);
+            }
+
+            current = next;
+            previous_ranges = parts.iter().map(|part| part.range()).collect();
+        }
+
+        MergeTree { nodes }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merge_tree() {
+        let model = Model::new(&[3.0, 1.0, 6.0, 4.0, 8.0, 9.0], 20.0).unwrap();
+        let tree = model.merge_tree();
+
+        // 6 initial parts and 5 merges
+        assert_eq!(tree.nodes().len(), 11);
+        assert_eq!(tree.roots().collect::<Vec<_>>(), vec![10]);
+
+        let leaves: Vec<_> = tree.nodes().iter().take(6).map(|node| node.range()).collect();
+        assert_eq!(leaves, vec![0..1, 1..2, 2..3, 3..4, 4..5, 5..6]);
+        assert!(tree.nodes()[1].accepts_water());
+        assert!(!tree.nodes()[2].accepts_water());
+
+        // the right basin fills up to the wall at 6.0 first
+        let first_merge = &tree.nodes()[6];
+        assert_eq!(first_merge.range(), 2..4);
+        assert_eq!(first_merge.children(), &[2, 3]);
+        assert_eq!(first_merge.level(), 6.0);
+        assert_eq!(tree.nodes()[3].spill_level(), Some(6.0));
+        assert_eq!(tree.nodes()[3].spill_time(), Some(first_merge.formed_at()));
+
+        let root = &tree.nodes()[10];
+        assert_eq!(root.range(), 0..6);
+        assert_eq!(root.level(), 9.0);
+        assert!(root.spill_time().is_none());
+
+        for (idx, node) in tree.nodes().iter().enumerate() {
+            for child in node.children() {
+                assert!(*child < idx);
+                assert_eq!(tree.nodes()[*child].parent(), Some(idx));
+            }
+        }
+    }
+
+    #[test]
+    fn test_export() {
+        let model = Model::new(&[2.0, 1.0, 2.0], 20.0).unwrap();
+        let tree = model.merge_tree();
+
+        assert_eq!(
+            tree.to_json(),
+            "{\"nodes\":[\
+            {\"id\":0,\"columns\":[0,1],\"formed_at\":0,\"level\":2,\"accepts_water\":false,\"spill_time\":0.3333333333333333,\"spill_level\":2,\"parent\":3,\"children\":[]},\
+            {\"id\":1,\"columns\":[1,2],\"formed_at\":0,\"level\":1,\"accepts_water\":true,\"spill_time\":0.3333333333333333,\"spill_level\":2,\"parent\":3,\"children\":[]},\
+            {\"id\":2,\"columns\":[2,3],\"formed_at\":0,\"level\":2,\"accepts_water\":false,\"spill_time\":0.3333333333333333,\"spill_level\":2,\"parent\":3,\"children\":[]},\
+            {\"id\":3,\"columns\":[0,3],\"formed_at\":0.3333333333333333,\"level\":2,\"accepts_water\":true,\"spill_time\":null,\"spill_level\":null,\"parent\":null,\"children\":[0,1,2]}\
+            ]}"
+        );
+
+        // the export is valid JSON, also for larger trees
+        let larger = Model::new(&[3.0, 1.0, 6.0, 4.0, 8.0, 9.0], 20.0).unwrap().merge_tree();
+        let json: serde_json::Value = serde_json::from_str(&larger.to_json()).unwrap();
+        assert_eq!(json["nodes"].as_array().unwrap().len(), larger.nodes().len());
+
+        let dot = tree.to_dot();
+        assert!(dot.starts_with("digraph merge_tree {\n"));
+        assert!(dot.contains("    n1 [shape=ellipse, label=\"columns 1..2\\nlevel 1\\nformed at 0\"];\n"));
+        assert!(dot.contains("    n3 -> n0 [label=\"t=0.3333333333333333\\nlevel 2\"];\n"));
+        assert!(dot.ends_with("}\n"));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_roundtrip() {
+        let model = Model::new(&[3.0, 1.0, 6.0, 4.0, 8.0, 9.0], 20.0).unwrap();
+        let tree = model.merge_tree();
+
+        let serialized = serde_json::to_string(&tree).unwrap();
+        let deserialized: MergeTree = serde_json::from_str(&serialized).unwrap();
+        assert_eq!(deserialized, tree);
+    }
+}
@@ -1,49 +1,112 @@
+use alloc::vec::Vec;
+#[cfg(feature = "serde")]
+use core::convert::TryFrom;
+use core::cmp::Ordering;
 use core::iter;
-use std::cmp::Ordering;
+use core::ops::Range;
 
-use anyhow::bail;
+use crate::builder::ModelBuilder;
+use crate::config::Config;
+use crate::error::ModelError;
+use crate::parts::Parts;
+use crate::scalar::Scalar;
 
-use crate::Height;
-use crate::parts::{Parts};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
+/// State of the parts between two sequential configuration changes
 #[derive(Debug)]
-pub struct Model {
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub(crate) struct Generation<T> {
+    pub(crate) start: T,
+
+    /// the last generation has no end
+    pub(crate) end: Option<T>,
+
+    pub(crate) parts: Parts<T>,
+}
+
+/// Serialized models are validated when deserializing, so they
+/// may be used right away without recalculation
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(
+    try_from = "UncheckedModel<T>",
+    bound(deserialize = "T: Scalar + Deserialize<'de>"),
+))]
+pub struct Model<T = f64> {
     /// generations represent the transition
     /// to another "merged" parts, when levels of neighbours
     /// become equal
-    initial_parts: Parts,
+    initial_parts: Parts<T>,
+
+    max_time: T,
 
-    max_time: f64,
+    config: Config<T>,
 
-    generations: Vec<((f64, f64), Parts)>,
+    generations: Vec<Generation<T>>,
 }
 
-impl Model {
-    fn calculate_generations(&mut self) -> anyhow::Result<()> {
-        let mut last_generation = (self.initial_parts.clone(), 0.0);
+/// Deserialized model, which was not validated yet
+#[cfg(feature = "serde")]
+#[derive(Deserialize)]
+struct UncheckedModel<T> {
+    initial_parts: Parts<T>,
+    max_time: T,
+    config: Config<T>,
+    generations: Vec<Generation<T>>,
+}
+
+#[cfg(feature = "serde")]
+impl<T: Scalar> TryFrom<UncheckedModel<T>> for Model<T> {
+    type Error = ModelError;
+
+    fn try_from(unchecked: UncheckedModel<T>) -> Result<Self, Self::Error> {
+        let model = Model {
+            initial_parts: unchecked.initial_parts,
+            max_time: unchecked.max_time,
+            config: unchecked.config,
+            generations: unchecked.generations,
+        };
+
+        model.validate()?;
+
+        Ok(model)
+    }
+}
+
+impl<T: Scalar> Model<T> {
+    fn calculate_generations(&mut self) -> Result<(), ModelError> {
+        let mut last_generation = (self.initial_parts.clone(), T::zero());
 
         loop {
             match last_generation.0.next_change() {
                 Some((change_indices, will_change_in)) => {
-                    let end_time = last_generation.1 + will_change_in;
-                    self.generations.push((
-                        (last_generation.1, end_time),
-                        last_generation.0.clone(),
-                    ));
+                    let end_time = last_generation.1.clone() + will_change_in.clone();
+                    if !end_time.is_finite() || !matches!(end_time.partial_cmp(&last_generation.1), Some(Ordering::Greater | Ordering::Equal)) {
+                        return Err(ModelError::Inconsistency("configuration change time is out of order"));
+                    }
+
+                    self.generations.push(Generation {
+                        start: last_generation.1,
+                        end: Some(end_time.clone()),
+                        parts: last_generation.0.clone(),
+                    });
 
-                    let last_state = last_generation.0.calculate_parts_at_rel_time(*will_change_in);
+                    let last_state = last_generation.0.calculate_parts_at_rel_time(will_change_in);
 
                     last_generation = (
-                        Parts::new_from_parts_and_changes(&last_state, change_indices)?,
+                        Parts::new_from_parts_and_changes(&last_state, change_indices, &self.config)?,
                         end_time
                     );
                 }
                 None => {
                     // final part
-                    self.generations.push((
-                        (last_generation.1,f64::MAX),
-                        last_generation.0,
-                    ));
+                    self.generations.push(Generation {
+                        start: last_generation.1,
+                        end: None,
+                        parts: last_generation.0,
+                    });
 
                     break;
                 }
@@ -53,17 +116,34 @@ impl Model {
         Ok(())
     }
 
-    pub fn new(v: &[Height], max_time: f64) -> anyhow::Result<Self> {
-        if v.iter().any(|item| {
-            item.is_infinite() || item.is_nan() || item.is_sign_negative()
-        }) {
-            bail!("should be a positive number");
-        }
+    /// Create the model with default options
+    ///
+    /// Use `ModelBuilder` to configure the model
+    pub fn new(v: &[T], max_time: T) -> Result<Self, ModelError> {
+        ModelBuilder::new()
+            .heights(v)
+            .max_time(max_time)
+            .build()
+    }
+
+    /// Create the model over other heights with the same options
+    pub(crate) fn rebuild(&self, v: &[T], max_time: T) -> Result<Self, ModelError> {
+        ModelBuilder::new()
+            .heights(v)
+            .max_time(max_time)
+            .merge_tolerance(self.config.tolerance.clone())
+            .time_tolerance(self.config.time_tolerance.clone())
+            .rain_rate(self.config.rain_rate.clone())
+            .build()
+    }
 
+    /// Create the model from the options, which were already validated
+    pub(crate) fn from_config(v: &[T], max_time: T, config: Config<T>) -> Result<Self, ModelError> {
         let mut obj = Model {
-            initial_parts: Parts::new(v)?,
+            initial_parts: Parts::new(v, &config)?,
             generations: Vec::new(),
             max_time,
+            config,
         };
 
         obj.calculate_generations()?;
@@ -71,37 +151,137 @@ impl Model {
         Ok(obj)
     }
 
+    /// Check that generations are monotonic in time and consistent with each other
+    #[cfg(feature = "serde")]
+    fn validate(&self) -> Result<(), ModelError> {
+        if !self.max_time.is_finite() || self.max_time.is_negative() {
+            return Err(ModelError::Corrupted("max time should be a positive number"));
+        }
+
+        if !self.config.tolerance.is_valid() || !self.config.time_tolerance.is_valid() {
+            return Err(ModelError::Corrupted("tolerance should be a positive number"));
+        }
+
+        if !self.config.rain_rate.is_finite() || self.config.rain_rate.is_negative() {
+            return Err(ModelError::Corrupted("rain rate should be a positive number"));
+        }
+
+        let num_columns = self.initial_parts.num_columns();
+        self.initial_parts.validate(num_columns)?;
+
+        let (first, last) = match (self.generations.first(), self.generations.last()) {
+            (Some(first), Some(last)) => (first, last),
+            _ => return Err(ModelError::Corrupted("no generations")),
+        };
+
+        if first.start != T::zero() {
+            return Err(ModelError::Corrupted("first generation should start at zero"));
+        }
+
+        if last.end.is_some() {
+            return Err(ModelError::Corrupted("last generation should have no end"));
+        }
+
+        for pair in self.generations.windows(2) {
+            match &pair[0].end {
+                Some(end) if *end == pair[1].start && pair[0].start <= *end => {}
+                _ => return Err(ModelError::Corrupted("generation times are not monotonic")),
+            }
+        }
+
+        for generation in &self.generations {
+            generation.parts.validate(num_columns)?;
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn config(&self) -> &Config<T> {
+        &self.config
+    }
+
+    pub(crate) fn max_time(&self) -> &T {
+        &self.max_time
+    }
+
+    /// All the generations ordered by time, including ones after the max time
+    pub(crate) fn generations(&self) -> &[Generation<T>] {
+        &self.generations
+    }
+
+    /// Parts of the terrain before any water was added
+    pub(crate) fn initial_parts(&self) -> &Parts<T> {
+        &self.initial_parts
+    }
+
+    /// Find the generation active at the provided time
+    ///
+    /// Returns the time relative to the start of the generation along with its parts
+    pub(crate) fn generation_at(&self, time: T) -> Result<(T, &Parts<T>), ModelError> {
+        if time.partial_cmp(&T::zero()).is_none() {
+            return Err(ModelError::InvalidTime);
+        }
 
-    pub fn calculate_levels(&self, time: f64) -> anyhow::Result<Vec<Height>> {
-        if time.is_sign_negative() {
-            bail!("time should not be negative");
+        if time.is_negative() {
+            return Err(ModelError::NegativeTime);
         }
 
         if time > self.max_time {
-            bail!("more then max time provided");
+            return Err(ModelError::TimeOutOfHorizon);
         }
 
-        let idx = self.generations.binary_search_by(|((probe_left, probe_right), _probe)| {
-            if time < *probe_left {
+        let idx = self.generations.binary_search_by(|probe| {
+            if time < probe.start {
                 Ordering::Greater
-            } else if time > *probe_right {
+            } else if matches!(&probe.end, Some(probe_end) if time > *probe_end) {
                 Ordering::Less
             } else {
                 Ordering::Equal
             }
-        }).unwrap();
+        }).map_err(|_| ModelError::Inconsistency("no generation covers the time"))?;
 
-        let ((segment_left, _), parts) = self.generations.get(idx).unwrap();
+        let generation = &self.generations[idx];
 
-        let offset = time - segment_left;
-        assert!(offset >= 0.0);
+        let offset = time - generation.start.clone();
+        if offset.is_negative() {
+            return Err(ModelError::Inconsistency("generation starts after the time"));
+        }
+
+        Ok((offset, &generation.parts))
+    }
+
+    /// Calculate levels of all the columns at the time
+    ///
+    /// Columns joined because of the merge tolerance share their mean height,
+    /// see `ModelBuilder::merge_tolerance`
+    pub fn calculate_levels(&self, time: T) -> Result<Vec<T>, ModelError> {
+        let (offset, parts) = self.generation_at(time)?;
 
-        Ok(parts.calculate_parts_at_rel_time(offset)
+        Ok(parts.calculate_parts_at_rel_time(&offset)
             .into_iter()
-            .map(|part| {
-                iter::repeat(part.height()).take(part.range().len())
+            .flat_map(|part| {
+                iter::repeat_n(part.height(), part.range().len())
+            })
+            .collect())
+    }
+
+    /// Calculate levels only for the provided range of columns
+    ///
+    /// Parts outside of the range are skipped, so the cost depends
+    /// on the size of the range rather than on the whole terrain
+    pub fn calculate_levels_range(&self, time: T, range: Range<usize>) -> Result<Vec<T>, ModelError> {
+        let num_columns = self.initial_parts.num_columns();
+        if range.start > range.end || range.end > num_columns {
+            return Err(ModelError::RangeOutOfBounds { range, num_columns });
+        }
+
+        let (offset, parts) = self.generation_at(time)?;
+
+        Ok(parts.calculate_parts_in_range_at_rel_time(&offset, range)
+            .into_iter()
+            .flat_map(|part| {
+                iter::repeat_n(part.height(), part.range().len())
             })
-            .flatten()
             .collect())
     }
 }
@@ -133,4 +313,95 @@ mod tests {
             assert_abs_diff_eq!(item, 9.0);
         }
     }
+
+    #[test]
+    fn test_levels_range() {
+        let model = Model::new(&[3.0, 1.0, 6.0, 4.0, 8.0, 9.0], 20.0).unwrap();
+
+        for time in [0.0, 0.3, 1.0, 2.5, 10.0] {
+            let all = model.calculate_levels(time).unwrap();
+            for start in 0..=all.len() {
+                for end in start..=all.len() {
+                    let r = model.calculate_levels_range(time, start..end).unwrap();
+                    assert_eq!(r, &all[start..end]);
+                }
+            }
+        }
+
+        assert_eq!(model.calculate_levels_range(0.0, 2..7), Err(ModelError::RangeOutOfBounds { range: 2..7, num_columns: 6 }));
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(Model::<f64>::new(&[], 1.0).unwrap_err(), ModelError::EmptyInput);
+        assert_eq!(Model::new(&[1.0, f64::NAN], 1.0).unwrap_err(), ModelError::InvalidHeight { index: 1 });
+        assert_eq!(Model::new(&[1.0, 2.0, -3.0], 1.0).unwrap_err(), ModelError::InvalidHeight { index: 2 });
+
+        let model = Model::new(&[1.0, 2.0], 1.0).unwrap();
+        assert_eq!(model.calculate_levels(-1.0), Err(ModelError::NegativeTime));
+        assert_eq!(model.calculate_levels(1.5), Err(ModelError::TimeOutOfHorizon));
+        assert_eq!(model.calculate_levels(f64::NAN), Err(ModelError::InvalidTime));
+    }
+
+    #[test]
+    fn test_degenerate_heights() {
+        let model = Model::new(&[f64::MAX, 0.0, f64::MAX], f64::MAX).unwrap();
+        assert_eq!(model.calculate_levels(1.0).unwrap(), vec![f64::MAX, 3.0, f64::MAX]);
+        assert_eq!(model.calculate_levels(f64::MAX / 4.0).unwrap(), vec![f64::MAX, f64::MAX * 0.75, f64::MAX]);
+
+        // the tiny bump is merged with the zeros around it right away
+        let model = Model::new(&[0.0, f64::MIN_POSITIVE, 0.0, 1e300], f64::MAX).unwrap();
+        let levels = model.calculate_levels(1.0).unwrap();
+        for level in &levels[..3] {
+            assert_abs_diff_eq!(*level, 4.0 / 3.0, epsilon = 1e-12);
+        }
+        assert_eq!(levels[3], 1e300);
+        // the volume of the rain overflows at the end of the horizon
+        assert!(model.calculate_levels(f64::MAX).unwrap().iter().all(|level| *level == f64::INFINITY));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_roundtrip() {
+        let model = Model::new(&[3.0, 1.0, 6.0, 4.0, 8.0, 9.0], 20.0).unwrap();
+        let serialized = serde_json::to_string(&model).unwrap();
+        let deserialized: Model = serde_json::from_str(&serialized).unwrap();
+
+        assert_eq!(deserialized.generations.len(), model.generations.len());
+        for time in [0.0, 0.3, 1.0, 2.5, 20.0] {
+            assert_eq!(deserialized.calculate_levels(time).unwrap(), model.calculate_levels(time).unwrap());
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_tampering() {
+        let model = Model::new(&[3.0, 1.0, 6.0, 4.0, 8.0, 9.0], 20.0).unwrap();
+        let value = serde_json::to_value(&model).unwrap();
+
+        let mut tampered = value.clone();
+        tampered["generations"][2]["start"] = serde_json::json!(100.0);
+        assert!(serde_json::from_value::<Model>(tampered).is_err());
+
+        let mut tampered = value.clone();
+        tampered["generations"][1]["parts"]["inner"][1]["merged_indices"]["start"] = serde_json::json!(3);
+        assert!(serde_json::from_value::<Model>(tampered).is_err());
+
+        let mut tampered = value.clone();
+        tampered["generations"].as_array_mut().unwrap().pop();
+        assert!(serde_json::from_value::<Model>(tampered).is_err());
+
+        let mut tampered = value;
+        tampered["max_time"] = serde_json::json!(-1.0);
+        assert!(serde_json::from_value::<Model>(tampered).is_err());
+    }
+
+    #[test]
+    fn test_f32() {
+        let model = Model::new(&[1.0f32, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0], 5.0).unwrap();
+        let r = model.calculate_levels(5.0).unwrap();
+        for item in r {
+            assert_abs_diff_eq!(item, 9.0, epsilon = 1e-5);
+        }
+    }
 }
\ No newline at end of file
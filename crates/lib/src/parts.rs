@@ -1,21 +1,29 @@
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cmp::Ordering;
 use core::mem;
-use std::cmp::Ordering;
-use std::ops::Range;
+use core::ops::Range;
 
-use anyhow::bail;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
-use crate::{Height, Index};
+use crate::Index;
+use crate::config::Config;
 use crate::direction::Direction;
+use crate::error::ModelError;
+use crate::scalar::Scalar;
+use crate::tolerance::Tolerance;
 
 #[derive(Debug, Clone, PartialEq)]
-pub struct Part {
-    height: Height,
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Part<T = f64> {
+    height: T,
     merged_indices: Range<usize>,
 }
 
-impl Part {
-    pub fn height(&self) -> Height {
-        self.height
+impl<T: Scalar> Part<T> {
+    pub fn height(&self) -> T {
+        self.height.clone()
     }
 
     pub fn range(&self) -> Range<usize> {
@@ -24,17 +32,18 @@ impl Part {
 }
 
 #[derive(Debug, Clone)]
-pub(crate) struct Parts {
-    inner: Vec<Part>,
-    velocities: Vec<(f64, usize)>,
-    next_change: Option<(Vec<(Index, Height)>, f64)>,
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub(crate) struct Parts<T = f64> {
+    inner: Vec<Part<T>>,
+    velocities: Vec<(T, usize)>,
+    next_change: Option<(Vec<(Index, T)>, T)>,
 }
 
 /// Find the index to which water will get from the provided one
 /// with the provided direction
 ///
 /// Returns destination index
-fn find_destination(parts: &[Part], current_idx: Index, direction: Direction) -> Option<Index> {
+fn find_destination<T: Scalar>(parts: &[Part<T>], current_idx: Index, direction: Direction) -> Option<Index> {
     if parts.len() <= current_idx {
         return None;
     }
@@ -45,18 +54,14 @@ fn find_destination(parts: &[Part], current_idx: Index, direction: Direction) ->
 
     while direction.set_index_to_next(&mut idx, 0..parts.len()) {
         match parts[idx].height.partial_cmp(last_value) {
-            Some(Ordering::Greater) => {
-                break;
-            }
-            Some(Ordering::Equal) => {
-                unreachable!("equal: {} == {}", parts[idx].height, last_value);
-            }
-            Some(Ordering::Less) => {
+            // equal neighbours are normally joined into a single part,
+            // if not, water keeps running over the flat surface
+            Some(Ordering::Less) | Some(Ordering::Equal) => {
                 last_value = &parts[idx].height;
                 found = Some(idx);
             }
-            None => {
-                panic!()
+            Some(Ordering::Greater) | None => {
+                break;
             }
         }
     };
@@ -74,35 +79,43 @@ fn find_destination(parts: &[Part], current_idx: Index, direction: Direction) ->
     })
 }
 
-fn is_accept_water(parts: &[Part], idx: usize) -> bool {
+/// Mean height of the columns of the run with the heights summing up to the sum
+fn mean<T: Scalar>(sum: T, indices: &Range<usize>) -> T {
+    sum / T::from_usize(indices.len())
+}
+
+fn is_accept_water<T: Scalar>(parts: &[Part<T>], idx: usize) -> bool {
     (idx == 0 || parts[idx - 1].height > parts[idx].height) &&
         (idx == parts.len() - 1 || parts[idx + 1].height > parts[idx].height)
 }
 
-fn calculate_filling_velocity(parts: &[Part]) -> Vec<(f64, usize)> {
-    let mut velocities = vec![(0.0, 1); parts.len()];
+fn calculate_filling_velocity<T: Scalar>(parts: &[Part<T>]) -> Vec<(T, usize)> {
+    let mut velocities: Vec<(T, usize)> = vec![(T::zero(), 1); parts.len()];
     for (idx, Part { merged_indices: range, .. }) in parts.iter().enumerate() {
+        let amount = T::from_usize(range.len());
         velocities[idx].1 = range.len();
         if is_accept_water(parts, idx) {
-            velocities[idx].0 += range.len() as f64;
+            velocities[idx].0 = velocities[idx].0.clone() + amount;
         } else {
             let maybe_right = find_destination(parts, idx, Direction::Right);
             let maybe_left = find_destination(parts, idx, Direction::Left);
 
             match (maybe_left, maybe_right) {
                 (Some(left), Some(right)) => {
-                    velocities[left].0 += range.len() as f64 / 2.0;
+                    let half = amount / T::from_usize(2);
+
+                    velocities[left].0 = velocities[left].0.clone() + half.clone();
                     velocities[left].1 = parts[left].merged_indices.len();
 
-                    velocities[right].0 += range.len() as f64 / 2.0;
+                    velocities[right].0 = velocities[right].0.clone() + half;
                     velocities[right].1 = parts[right].merged_indices.len();
                 }
                 (Some(left), None) => {
-                    velocities[left].0 += range.len() as f64;
+                    velocities[left].0 = velocities[left].0.clone() + amount;
                     velocities[left].1 = parts[left].merged_indices.len();
                 }
                 (None, Some(right)) => {
-                    velocities[right].0 += range.len() as f64;
+                    velocities[right].0 = velocities[right].0.clone() + amount;
                     velocities[right].1 = parts[right].merged_indices.len();
                 }
                 (None, None) => {}
@@ -121,163 +134,285 @@ fn calculate_filling_velocity(parts: &[Part]) -> Vec<(f64, usize)> {
 ///
 /// Index is as stored in parts slice
 ///
-fn calculate_next_configuration_change(parts: &[Part], velocities: &[(f64, usize)]) -> Option<(Vec<(Index, Height)>, f64)> {
-    let mut min_time_to_reach_nearest: Option<(Vec<(Index, Height)>, f64)> = None;
+fn calculate_next_configuration_change<T: Scalar>(parts: &[Part<T>], velocities: &[(T, usize)], time_tolerance: &Tolerance<T>) -> Option<(Vec<(Index, T)>, T)> {
+    let mut changes: Vec<(Index, T, T)> = Vec::new();
     for (idx, (merged_velocity, num_parts)) in velocities.iter().enumerate() {
-        let velocity  = *merged_velocity / *num_parts as f64;
-        if velocity > 0.0 {
+        let velocity = merged_velocity.clone() / T::from_usize(*num_parts);
+        if velocity > T::zero() {
             let left_diff = if idx > 0 && parts[idx].height < parts[idx - 1].height {
-                Some(parts[idx - 1].height - parts[idx].height)
+                Some(parts[idx - 1].height.clone() - parts[idx].height.clone())
             } else {
                 None
             };
 
             let right_diff = if idx < parts.len() - 1 && parts[idx].height < parts[idx + 1].height {
-                Some(parts[idx + 1].height - parts[idx].height)
+                Some(parts[idx + 1].height.clone() - parts[idx].height.clone())
             } else {
                 None
             };
 
             let (nearest_height_diff_around, will_be_height) = match (left_diff, right_diff) {
                 (Some(left), Some(right)) if left <= right =>
-                    (left, parts[idx - 1].height),
-                (Some(_left), Some(right)) => (right, parts[idx + 1].height),
-                (Some(left), None) => (left, parts[idx - 1].height),
-                (None, Some(right)) => (right, parts[idx + 1].height),
+                    (left, parts[idx - 1].height.clone()),
+                (Some(_left), Some(right)) => (right, parts[idx + 1].height.clone()),
+                (Some(left), None) => (left, parts[idx - 1].height.clone()),
+                (None, Some(right)) => (right, parts[idx + 1].height.clone()),
                 (None, None) => continue
             };
 
-            let time_to_reach_nearest = nearest_height_diff_around as f64 / velocity;
-            match &mut min_time_to_reach_nearest {
-                Some((minimal_indices, min_known_time)) if approx::abs_diff_eq!(time_to_reach_nearest, *min_known_time, epsilon = f64::EPSILON) => {
-                    minimal_indices.push((idx, will_be_height));
-                }
-                Some((_, min_known_time)) if time_to_reach_nearest < *min_known_time => {
-                    min_time_to_reach_nearest = Some((vec![(idx, will_be_height)], time_to_reach_nearest));
-                }
-                None => {
-                    min_time_to_reach_nearest = Some((vec![(idx, will_be_height)], time_to_reach_nearest));
-                }
-                _ => {}
-            }
+            changes.push((idx, will_be_height, nearest_height_diff_around / velocity));
         }
     }
 
-    min_time_to_reach_nearest
+    // events within the tolerance of the earliest one are simultaneous with it
+    let min_time = changes.iter()
+        .map(|(_, _, time)| time)
+        .fold(None, |min: Option<&T>, time| match min {
+            Some(min) if min <= time => Some(min),
+            _ => Some(time),
+        })?
+        .clone();
+
+    let simultaneous = changes.into_iter()
+        .filter(|(_, _, time)| time_tolerance.is_equal(time, &min_time))
+        .map(|(idx, will_be_height, _)| (idx, will_be_height))
+        .collect();
+
+    Some((simultaneous, min_time))
 }
 
-impl Parts {
+impl<T: Scalar> Parts<T> {
     /// Create new Parts from the provided configuration
     ///
     /// This will join all sequential duplicates
-    pub(crate) fn new(v: &[Height]) -> anyhow::Result<Self> {
+    pub(crate) fn new(v: &[T], config: &Config<T>) -> Result<Self, ModelError> {
         if v.is_empty() {
-            bail!("should not be empty");
+            return Err(ModelError::EmptyInput);
         }
+        // each run of nearly equal columns is compared with its first column
+        // and gets their mean height, so the volume of the terrain stays the same
         let mut parts = Vec::with_capacity(v.len());
-        let mut cur_height = v[0];
+        let mut first = &v[0];
+        let mut sum = v[0].clone();
         let mut cur_indices = 0..1;
-        for (idx, part) in v.iter().enumerate() {
-            if idx == 0 {
-                continue;
-            }
-
-            if approx::abs_diff_eq!(*part, cur_height, epsilon = f64::EPSILON) {
+        for (idx, part) in v.iter().enumerate().skip(1) {
+            if config.tolerance.is_equal(part, first) {
                 cur_indices.end += 1;
+                sum = sum + part.clone();
             } else {
                 let indices = mem::replace(&mut cur_indices, idx..idx + 1);
-                parts.push(Part { height: cur_height, merged_indices: indices });
-                cur_height = *part;
+                let height = mean(mem::replace(&mut sum, part.clone()), &indices);
+                parts.push(Part { height, merged_indices: indices });
+                first = part;
             }
         }
 
-        parts.push(Part { height: *v.last().unwrap(), merged_indices: cur_indices });
+        let height = mean(sum, &cur_indices);
+        parts.push(Part { height, merged_indices: cur_indices });
 
-        let velocities = calculate_filling_velocity(&parts);
-        let next_change = calculate_next_configuration_change(&parts, &velocities);
-
-        Ok(Self {
-            inner: parts,
-            velocities,
-            next_change,
-        })
+        Ok(Self::from_merged_parts(parts, config))
     }
 
     /// Create new Parts from the provided configuration
     ///
     /// This will join all sequential duplicates
-    pub(crate) fn new_from_parts_and_changes(v: &[Part], changes: &[(Index, Height)]) -> anyhow::Result<Self> {
+    pub(crate) fn new_from_parts_and_changes(v: &[Part<T>], changes: &[(Index, T)], config: &Config<T>) -> Result<Self, ModelError> {
         if v.is_empty() {
-            bail!("should not be empty");
+            return Err(ModelError::Inconsistency("no parts to apply changes to"));
         }
 
-        let mut parts_collector: Vec<Option<Part>> = v.iter().map(|v| Some(v.clone())).collect();
+        let mut parts_collector: Vec<Option<Part<T>>> = v.iter().map(|v| Some(v.clone())).collect();
 
         for (changed_idx, changed_height) in changes {
-            let was_part = parts_collector[*changed_idx].take().unwrap();
-            if *changed_idx >= 1 && Some(*changed_height) == v.get(*changed_idx - 1).map(|v| v.height) {
-                let part_to_join_to = &mut parts_collector[*changed_idx - 1].as_mut().unwrap().merged_indices;
-                assert_eq!(part_to_join_to.end, was_part.merged_indices.start);
+            let changed_idx = *changed_idx;
+            let was_part = parts_collector.get_mut(changed_idx)
+                .and_then(Option::take)
+                .ok_or(ModelError::Inconsistency("changed part is missing"))?;
+
+            let is_reached = |idx: Index| v.get(idx).is_some_and(|v| config.tolerance.is_equal(&v.height, changed_height));
+
+            let join_to_idx = if changed_idx >= 1 && is_reached(changed_idx - 1) {
+                changed_idx - 1
+            } else if is_reached(changed_idx + 1) {
+                changed_idx + 1
+            } else {
+                return Err(ModelError::Inconsistency("expected a change but no change may occur"));
+            };
+
+            let part_to_join_to = &mut parts_collector.get_mut(join_to_idx)
+                .and_then(Option::as_mut)
+                .ok_or(ModelError::Inconsistency("part to join to is missing"))?
+                .merged_indices;
+
+            if part_to_join_to.end == was_part.merged_indices.start {
                 part_to_join_to.end = was_part.merged_indices.end;
-            } else if *changed_idx + 1 < v.len() && Some(*changed_height) == v.get(*changed_idx + 1).map(|v| v.height) {
-                let part_to_join_to = &mut parts_collector[*changed_idx + 1].as_mut().unwrap().merged_indices;
-                assert_eq!(was_part.merged_indices.end, part_to_join_to.start);
+            } else if was_part.merged_indices.end == part_to_join_to.start {
                 part_to_join_to.start = was_part.merged_indices.start;
             } else {
-                unreachable!("expected a change but no change may occur");
+                return Err(ModelError::Inconsistency("joined parts are not adjacent"));
             }
         }
 
-        // it's still possible that near duplicates appear after merge. let's loop until all duplicates are eliminated
-        loop {
-            let was_len = parts_collector.len();
-            parts_collector = parts_collector.into_iter().filter(|v| v.is_some()).collect();
-            assert!(!parts_collector.is_empty());
-            if parts_collector.len() == was_len {
-                // no changes occurred
-                break;
-            }
-            for idx in 1..parts_collector.len() {
-                if parts_collector[idx - 1].is_some() &&
-                    parts_collector[idx].is_some() &&
-                    approx::abs_diff_eq!(parts_collector[idx - 1].as_ref().unwrap().height, parts_collector[idx].as_ref().unwrap().height, epsilon = f64::EPSILON) {
-                    let right = parts_collector[idx].take().unwrap();
-
-                    assert_eq!(parts_collector[idx - 1].as_ref().unwrap().merged_indices.end, right.merged_indices.start);
-                    parts_collector[idx - 1].as_mut().unwrap().merged_indices.end = right.merged_indices.end;
+        // it's still possible that near duplicates appear after merge, so join them as well
+        let mut parts: Vec<Part<T>> = Vec::with_capacity(parts_collector.len());
+        for part in parts_collector.into_iter().flatten() {
+            match parts.last_mut() {
+                Some(last) if config.tolerance.is_equal(&last.height, &part.height) => {
+                    if last.merged_indices.end != part.merged_indices.start {
+                        return Err(ModelError::Inconsistency("duplicate parts are not adjacent"));
+                    }
+                    let (last_width, width) = (T::from_usize(last.merged_indices.len()), T::from_usize(part.merged_indices.len()));
+                    last.height = (last.height.clone() * last_width.clone() + part.height.clone() * width.clone()) / (last_width + width);
+                    last.merged_indices.end = part.merged_indices.end;
                 }
+                _ => parts.push(part),
             }
         }
 
-        let parts: Vec<_> = parts_collector.into_iter().flatten().collect();
+        Ok(Self::from_merged_parts(parts, config))
+    }
 
-        let velocities = calculate_filling_velocity(&parts);
-        let next_change = calculate_next_configuration_change(&parts, &velocities);
+    /// Calculate velocities and the next change for parts without duplicates
+    fn from_merged_parts(parts: Vec<Part<T>>, config: &Config<T>) -> Self {
+        let mut velocities = calculate_filling_velocity(&parts);
+        for (velocity, _) in velocities.iter_mut() {
+            *velocity = velocity.clone() * config.rain_rate.clone();
+        }
 
-        Ok(Self {
+        let next_change = calculate_next_configuration_change(&parts, &velocities, &config.time_tolerance);
+
+        Self {
             inner: parts,
             velocities,
             next_change,
-        })
+        }
     }
 
-    pub(crate) fn calculate_parts_at_rel_time(&self, time: f64) -> Vec<Part> {
+    pub(crate) fn calculate_parts_at_rel_time(&self, time: &T) -> Vec<Part<T>> {
         let mut new_parts = self.inner.clone();
 
         for (new_part, (velocity, num_parts)) in new_parts.iter_mut().zip(self.velocities.iter()) {
-            new_part.height += velocity * time / *num_parts as f64;
+            new_part.height = new_part.height.clone() + velocity.clone() * time.clone() / T::from_usize(*num_parts);
         }
 
         new_parts
     }
 
-    pub(crate) fn next_change(&self) -> &Option<(Vec<(Index, Height)>, f64)> {
+    /// Same as `calculate_parts_at_rel_time`, but only for the parts overlapping
+    /// the provided column range
+    ///
+    /// Ranges of the returned parts are clipped to the requested range
+    pub(crate) fn calculate_parts_in_range_at_rel_time(&self, time: &T, range: Range<usize>) -> Vec<Part<T>> {
+        let first = self.inner.partition_point(|part| part.merged_indices.end <= range.start);
+
+        self.inner[first..].iter()
+            .zip(self.velocities[first..].iter())
+            .take_while(|(part, _)| part.merged_indices.start < range.end)
+            .map(|(part, (velocity, num_parts))| Part {
+                height: part.height.clone() + velocity.clone() * time.clone() / T::from_usize(*num_parts),
+                merged_indices: part.merged_indices.start.max(range.start)..part.merged_indices.end.min(range.end),
+            })
+            .collect()
+    }
+
+    /// Check if the part at the index is a local minimum, which retains all the water reaching it
+    pub(crate) fn accepts_water(&self, idx: Index) -> bool {
+        idx < self.inner.len() && is_accept_water(&self.inner, idx)
+    }
+
+    /// Find the parts to which water runs off from the part at the index
+    ///
+    /// Returns left and right destinations, both are None for parts accepting water
+    pub(crate) fn runoff_destinations(&self, idx: Index) -> (Option<Index>, Option<Index>) {
+        if idx >= self.inner.len() || is_accept_water(&self.inner, idx) {
+            return (None, None);
+        }
+
+        (
+            find_destination(&self.inner, idx, Direction::Left),
+            find_destination(&self.inner, idx, Direction::Right),
+        )
+    }
+
+    /// Find the neighbour of the part at the index, whose height it reaches with the next change
+    ///
+    /// Left neighbour is preferred, when both have the same height, as in `calculate_next_configuration_change`
+    pub(crate) fn reached_neighbour(&self, idx: Index, will_be_height: &T, tolerance: &Tolerance<T>) -> Option<Index> {
+        let left = idx.checked_sub(1)
+            .filter(|left| self.inner.get(*left).is_some_and(|part| tolerance.is_equal(&part.height, will_be_height)));
+        let right = Some(idx + 1).filter(|right| *right < self.inner.len());
+
+        left.or(right)
+    }
+
+    /// Amount of water reaching each part per unit of time along with the number of its columns
+    pub(crate) fn velocities(&self) -> &[(T, usize)] {
+        &self.velocities
+    }
+
+    /// Same as `velocities`, but for the unit rain rate
+    pub(crate) fn unit_velocities(&self) -> Vec<(T, usize)> {
+        calculate_filling_velocity(&self.inner)
+    }
+
+    /// Total number of columns covered by the parts
+    pub(crate) fn num_columns(&self) -> usize {
+        self.inner.last().map(|part| part.merged_indices.end).unwrap_or(0)
+    }
+
+    pub(crate) fn next_change(&self) -> &Option<(Vec<(Index, T)>, T)> {
         &self.next_change
     }
+
+    /// Check the invariants of parts, which were not built by this crate
+    ///
+    /// Parts should cover all the columns contiguously and carry
+    /// consistent velocities and next change
+    #[cfg(feature = "serde")]
+    pub(crate) fn validate(&self, num_columns: usize) -> Result<(), ModelError> {
+        let mut expected_start = 0;
+        for part in &self.inner {
+            if part.merged_indices.start != expected_start || part.merged_indices.is_empty() {
+                return Err(ModelError::Corrupted("part ranges are not contiguous"));
+            }
+            if !part.height.is_finite() || part.height.is_negative() {
+                return Err(ModelError::Corrupted("part height should be a positive number"));
+            }
+            expected_start = part.merged_indices.end;
+        }
+
+        if self.inner.is_empty() || expected_start != num_columns {
+            return Err(ModelError::Corrupted("parts do not cover all columns"));
+        }
+
+        if self.velocities.len() != self.inner.len() {
+            return Err(ModelError::Corrupted("velocities do not match parts"));
+        }
+
+        for ((velocity, num_parts), part) in self.velocities.iter().zip(self.inner.iter()) {
+            if !velocity.is_finite() || velocity.is_negative() || *num_parts != part.merged_indices.len() {
+                return Err(ModelError::Corrupted("velocities do not match parts"));
+            }
+        }
+
+        if let Some((changes, time)) = &self.next_change {
+            if !time.is_finite() || time.is_negative() {
+                return Err(ModelError::Corrupted("next change time should be a positive number"));
+            }
+            if changes.is_empty() || changes.windows(2).any(|pair| pair[0].0 >= pair[1].0) {
+                return Err(ModelError::Corrupted("next change indices are not sorted"));
+            }
+            if changes.iter().any(|(idx, _)| *idx >= self.inner.len()) {
+                return Err(ModelError::Corrupted("next change index is out of bounds"));
+            }
+        }
+
+        Ok(())
+    }
 }
 
-impl AsRef<[Part]> for Parts {
-    fn as_ref(&self) -> &[Part] {
+impl<T> AsRef<[Part<T>]> for Parts<T> {
+    fn as_ref(&self) -> &[Part<T>] {
         self.inner.as_ref()
     }
 }
@@ -290,7 +425,7 @@ mod tests {
 
     #[test]
     fn test_example() {
-        let parts = Parts::new(&[3.0, 1.0, 6.0, 4.0, 8.0, 9.0]).unwrap();
+        let parts = Parts::new(&[3.0, 1.0, 6.0, 4.0, 8.0, 9.0], &Config::default()).unwrap();
 
         assert_eq!(find_destination(parts.as_ref(), 0, Direction::Right).unwrap(), 1);
         assert!(find_destination(parts.as_ref(), 0, Direction::Left).is_none());
@@ -315,13 +450,13 @@ mod tests {
         let velocities = calculate_filling_velocity(parts.as_ref());
         assert_eq!(velocities, vec![(0.0, 1), (2.5, 1), (0.0, 1), (3.5, 1), (0.0, 1), (0.0, 1)]);
 
-        let (next_configuration_changes, time_before_change) = calculate_next_configuration_change(parts.as_ref(), &velocities)
+        let (next_configuration_changes, time_before_change) = calculate_next_configuration_change(parts.as_ref(), &velocities, &Tolerance::default())
             .unwrap();
 
         assert_eq!(next_configuration_changes, vec![(3, 6.0)]);
         assert_abs_diff_eq!(time_before_change, 2.0f64 / 3.5f64);
 
-        let next_parts = Parts::new_from_parts_and_changes(parts.as_ref(), &next_configuration_changes).unwrap();
+        let next_parts = Parts::new_from_parts_and_changes(parts.as_ref(), &next_configuration_changes, &Config::default()).unwrap();
         assert_eq!(next_parts.as_ref(), vec![
             Part {
                 height: 3.0,
@@ -346,13 +481,13 @@ mod tests {
         ]);
 
         let velocities = calculate_filling_velocity(next_parts.as_ref());
-        let (next_configuration_changes, time_before_change) = calculate_next_configuration_change(next_parts.as_ref(), &velocities)
+        let (next_configuration_changes, time_before_change) = calculate_next_configuration_change(next_parts.as_ref(), &velocities, &Tolerance::default())
             .unwrap();
 
         assert_eq!(next_configuration_changes, vec![(1, 3.0)]);
         assert_abs_diff_eq!(time_before_change, 2.0f64 / 6.0f64);
 
-        let next_parts = Parts::new_from_parts_and_changes(next_parts.as_ref(), &next_configuration_changes).unwrap();
+        let next_parts = Parts::new_from_parts_and_changes(next_parts.as_ref(), &next_configuration_changes, &Config::default()).unwrap();
         assert_eq!(next_parts.as_ref(), vec![
             Part {
                 height: 3.0,
@@ -374,13 +509,13 @@ mod tests {
 
         let velocities = calculate_filling_velocity(next_parts.as_ref());
 
-        let (next_configuration_changes, time_before_change) = calculate_next_configuration_change(next_parts.as_ref(), &velocities)
+        let (next_configuration_changes, time_before_change) = calculate_next_configuration_change(next_parts.as_ref(), &velocities, &Tolerance::default())
             .unwrap();
 
         assert_eq!(next_configuration_changes, vec![(0, 6.0)]);
         assert_abs_diff_eq!(time_before_change, 3.0f64 / 3.0f64);
 
-        let next_parts = Parts::new_from_parts_and_changes(next_parts.as_ref(), &next_configuration_changes).unwrap();
+        let next_parts = Parts::new_from_parts_and_changes(next_parts.as_ref(), &next_configuration_changes, &Config::default()).unwrap();
         assert_eq!(next_parts.as_ref(), vec![
             Part {
                 height: 6.0,
@@ -398,13 +533,13 @@ mod tests {
 
         let velocities = calculate_filling_velocity(next_parts.as_ref());
 
-        let (next_configuration_changes, time_before_change) = calculate_next_configuration_change(next_parts.as_ref(), &velocities)
+        let (next_configuration_changes, time_before_change) = calculate_next_configuration_change(next_parts.as_ref(), &velocities, &Tolerance::default())
             .unwrap();
 
         assert_eq!(next_configuration_changes, vec![(0, 8.0)]);
         assert_abs_diff_eq!(time_before_change, 1.0f64 / 3.0f64 * 4.0f64);
 
-        let next_parts = Parts::new_from_parts_and_changes(next_parts.as_ref(), &next_configuration_changes).unwrap();
+        let next_parts = Parts::new_from_parts_and_changes(next_parts.as_ref(), &next_configuration_changes, &Config::default()).unwrap();
         assert_eq!(next_parts.as_ref(), vec![
             Part {
                 height: 8.0,
@@ -418,13 +553,13 @@ mod tests {
 
         let velocities = calculate_filling_velocity(next_parts.as_ref());
 
-        let (next_configuration_changes, time_before_change) = calculate_next_configuration_change(next_parts.as_ref(), &velocities)
+        let (next_configuration_changes, time_before_change) = calculate_next_configuration_change(next_parts.as_ref(), &velocities, &Tolerance::default())
             .unwrap();
 
         assert_eq!(next_configuration_changes, vec![(0, 9.0)]);
         assert_abs_diff_eq!(time_before_change, 1.0f64 / 6.0f64 * 5.0);
 
-        let next_parts = Parts::new_from_parts_and_changes(next_parts.as_ref(), &next_configuration_changes).unwrap();
+        let next_parts = Parts::new_from_parts_and_changes(next_parts.as_ref(), &next_configuration_changes, &Config::default()).unwrap();
         assert_eq!(next_parts.as_ref(), vec![
             Part {
                 height: 9.0,
@@ -433,12 +568,12 @@ mod tests {
         ]);
 
         let velocities = calculate_filling_velocity(next_parts.as_ref());
-        assert!(calculate_next_configuration_change(next_parts.as_ref(), &velocities).is_none());
+        assert!(calculate_next_configuration_change(next_parts.as_ref(), &velocities, &Tolerance::default()).is_none());
     }
 
     #[test]
     fn test_with_duplicates() {
-        let parts = Parts::new(&[3.0, 1.0, 1.0, 2.0, 2.0, 4.0]).unwrap();
+        let parts = Parts::new(&[3.0, 1.0, 1.0, 2.0, 2.0, 4.0], &Config::default()).unwrap();
 
         assert_eq!(find_destination(parts.as_ref(), 2, Direction::Left).unwrap(), 1);
         assert_eq!(find_destination(parts.as_ref(), 3, Direction::Left).unwrap(), 1);
@@ -446,7 +581,7 @@ mod tests {
         let velocities = calculate_filling_velocity(parts.as_ref());
         assert_eq!(velocities, vec![(0.0, 1), (6.0, 2), (0.0, 2), (0.0, 1)]);
 
-        let (next_configuration_change_indices, time_before_change) = calculate_next_configuration_change(parts.as_ref(), &velocities)
+        let (next_configuration_change_indices, time_before_change) = calculate_next_configuration_change(parts.as_ref(), &velocities, &Tolerance::default())
             .unwrap();
 
         assert_eq!(next_configuration_change_indices, vec![(1, 2.0)]);
@@ -455,33 +590,54 @@ mod tests {
 
     #[test]
     fn test_with_multiple_parts_reaching_configuration_change_at_the_same_time() {
-        let parts = Parts::new(&[3.0, 2.0, 4.0, 3.0, 4.0]).unwrap();
+        let parts = Parts::new(&[3.0, 2.0, 4.0, 3.0, 4.0], &Config::default()).unwrap();
 
         let velocities = calculate_filling_velocity(parts.as_ref());
         assert_eq!(velocities, vec![(0.0, 1), (2.5, 1), (0.0, 1), (2.5, 1), (0.0, 1)]);
 
-        let (next_configuration_change_idx, _time_before_change) = calculate_next_configuration_change(parts.as_ref(), &velocities)
+        let (next_configuration_change_idx, _time_before_change) = calculate_next_configuration_change(parts.as_ref(), &velocities, &Tolerance::default())
             .unwrap();
 
         assert_eq!(next_configuration_change_idx, vec![(1, 3.0), (3, 4.0)]);
     }
 
+    #[test]
+    fn test_nearly_simultaneous_changes() {
+        // the left basin reaches its rim at 0.404, the right one at 0.4
+        let config = Config {
+            tolerance: Tolerance::Absolute(0.005),
+            ..Config::default()
+        };
+        let parts = Parts::new(&[4.0, 2.99, 4.0, 2.0, 3.0], &config).unwrap();
+        let velocities = calculate_filling_velocity(parts.as_ref());
+
+        // the tolerance of the heights does not apply to the times
+        let (changes, time) = calculate_next_configuration_change(parts.as_ref(), &velocities, &config.time_tolerance).unwrap();
+        assert_eq!(changes, vec![(3, 3.0)]);
+        assert_abs_diff_eq!(time, 0.4);
+
+        // the later event found first does not delay the earlier one
+        let (changes, time) = calculate_next_configuration_change(parts.as_ref(), &velocities, &Tolerance::Absolute(0.01)).unwrap();
+        assert_eq!(changes, vec![(1, 4.0), (3, 3.0)]);
+        assert_abs_diff_eq!(time, 0.4);
+    }
+
     #[test]
     fn test_single_element() {
-        let parts = Parts::new(&[3.0]).unwrap();
+        let parts = Parts::new(&[3.0], &Config::default()).unwrap();
         assert!(find_destination(parts.as_ref(), 0, Direction::Right).is_none());
 
 
         let velocities = calculate_filling_velocity(parts.as_ref());
         assert_eq!(velocities, vec![(1.0, 1)]);
 
-        assert!(calculate_next_configuration_change(parts.as_ref(), &velocities)
+        assert!(calculate_next_configuration_change(parts.as_ref(), &velocities, &Tolerance::default())
             .is_none());
     }
 
     #[test]
     fn test_multiple_elements() {
-        let parts = Parts::new(&[1.0, 1.0, 3.0]).unwrap();
+        let parts = Parts::new(&[1.0, 1.0, 3.0], &Config::default()).unwrap();
         let velocities = calculate_filling_velocity(parts.as_ref());
 
         assert_eq!(velocities, vec![(3.0, 2), (0.0, 1)]);
@@ -489,6 +645,6 @@ mod tests {
 
     #[test]
     fn test_empty() {
-        assert!(Parts::new(&[]).is_err());
+        assert!(Parts::<f64>::new(&[], &Config::default()).is_err());
     }
 }
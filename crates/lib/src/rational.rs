@@ -0,0 +1,151 @@
+use alloc::format;
+use alloc::string::ToString;
+use core::fmt;
+
+use num_bigint::BigInt;
+use num_rational::BigRational;
+use num_traits::{Signed, ToPrimitive, Zero};
+
+use crate::scalar::Scalar;
+
+/// Arbitrary precision rational number
+///
+/// Merges and simultaneous events are detected exactly when the model
+/// is built over this type.
+pub type Rational = BigRational;
+
+impl Scalar for Rational {
+    fn zero() -> Self {
+        Zero::zero()
+    }
+
+    fn from_usize(v: usize) -> Self {
+        Rational::from_integer(BigInt::from(v))
+    }
+
+    fn is_finite(&self) -> bool {
+        true
+    }
+
+    fn is_negative(&self) -> bool {
+        Signed::is_negative(self)
+    }
+
+    fn to_f64(&self) -> f64 {
+        to_f64(self)
+    }
+
+    fn default_tolerance() -> Self {
+        Zero::zero()
+    }
+}
+
+/// Error returned when the string is not a decimal number
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseDecimalError;
+
+impl fmt::Display for ParseDecimalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "bad decimal number")
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for ParseDecimalError {}
+
+/// Parse a decimal number (e.g. `-12.375`) into the exact rational
+pub fn parse_decimal(s: &str) -> Result<Rational, ParseDecimalError> {
+    let (negative, digits) = match s.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, s.strip_prefix('+').unwrap_or(s)),
+    };
+
+    let (int_part, frac_part) = match digits.split_once('.') {
+        Some((int_part, frac_part)) => (int_part, frac_part),
+        None => (digits, ""),
+    };
+
+    if int_part.is_empty() && frac_part.is_empty() {
+        return Err(ParseDecimalError);
+    }
+
+    if !int_part.bytes().chain(frac_part.bytes()).all(|b| b.is_ascii_digit()) {
+        return Err(ParseDecimalError);
+    }
+
+    let numer: BigInt = format!("{}{}", int_part, frac_part)
+        .parse()
+        .map_err(|_| ParseDecimalError)?;
+    let denom = BigInt::from(10u32).pow(frac_part.len() as u32);
+
+    let value = Rational::new(numer, denom);
+
+    Ok(if negative { -value } else { value })
+}
+
+/// Convert a float into the rational with the same shortest decimal representation
+///
+/// `0.1` becomes exactly `1/10`, rather than the nearest binary fraction.
+/// Returns None for infinite and NaN values.
+pub fn from_f64(v: f64) -> Option<Rational> {
+    if !v.is_finite() {
+        return None;
+    }
+
+    parse_decimal(&v.to_string()).ok()
+}
+
+/// Convert the rational into the nearest float
+pub fn to_f64(v: &Rational) -> f64 {
+    ToPrimitive::to_f64(v).unwrap_or(f64::NAN)
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_abs_diff_eq;
+
+    use crate::Model;
+
+    use super::*;
+
+    fn rationals(v: &[f64]) -> Vec<Rational> {
+        v.iter().map(|v| from_f64(*v).unwrap()).collect()
+    }
+
+    #[test]
+    fn test_parse_decimal() {
+        assert_eq!(parse_decimal("12.375").unwrap(), Rational::new(BigInt::from(99), BigInt::from(8)));
+        assert_eq!(parse_decimal("-0.1").unwrap(), Rational::new(BigInt::from(-1), BigInt::from(10)));
+        assert_eq!(parse_decimal("+7").unwrap(), Rational::from_integer(BigInt::from(7)));
+        assert_eq!(parse_decimal(".5").unwrap(), Rational::new(BigInt::from(1), BigInt::from(2)));
+        assert!(parse_decimal("").is_err());
+        assert!(parse_decimal(".").is_err());
+        assert!(parse_decimal("1e5").is_err());
+        assert!(parse_decimal("1.2.3").is_err());
+        assert_eq!(from_f64(0.1).unwrap(), Rational::new(BigInt::from(1), BigInt::from(10)));
+        assert!(from_f64(f64::NAN).is_none());
+    }
+
+    #[test]
+    fn test_exact_levels() {
+        let model = Model::new(&rationals(&[1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0]), from_f64(5.0).unwrap()).unwrap();
+        for item in model.calculate_levels(from_f64(5.0).unwrap()).unwrap() {
+            assert_eq!(item, from_f64(9.0).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_matches_float() {
+        let heights = [3.0, 1.0, 6.0, 4.0, 8.0, 9.0, 0.1, 0.2, 0.3];
+        let float_model = Model::new(&heights, 20.0).unwrap();
+        let exact_model = Model::new(&rationals(&heights), from_f64(20.0).unwrap()).unwrap();
+
+        for time in [0.0, 0.25, 1.0, 3.3, 20.0] {
+            let expected = exact_model.calculate_levels(from_f64(time).unwrap()).unwrap();
+            let actual = float_model.calculate_levels(time).unwrap();
+            for (expected, actual) in expected.iter().zip(actual) {
+                assert_abs_diff_eq!(to_f64(expected), actual, epsilon = 1e-9);
+            }
+        }
+    }
+}
@@ -0,0 +1,289 @@
+use alloc::vec::Vec;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+use crate::error::ModelError;
+use crate::model::Model;
+use crate::scalar::Scalar;
+use crate::tolerance::Tolerance;
+
+/// Straightforward fixed step simulation of the rain, the specification of the model
+///
+/// Each step the rain falling on each column within the step is poured onto it at once.
+/// Water runs over the flat surface and down the slopes, a flat top with lower surface on both
+/// sides splits it in halves, as `find_destination` does. Water fills the basin it reaches
+/// and spills over its lower rim, when the basin is full. The edges of the terrain are walls.
+#[derive(Debug, Clone)]
+pub struct Reference<T = f64> {
+    surface: Vec<T>,
+    time: T,
+    step: T,
+    config: Config<T>,
+}
+
+impl<T: Scalar> Reference<T> {
+    pub fn time(&self) -> T {
+        self.time.clone()
+    }
+
+    /// Levels of the water at the current time
+    pub fn levels(&self) -> &[T] {
+        &self.surface
+    }
+
+    /// Run the simulation until the time with the fixed steps, the last one may be shorter
+    pub fn advance_to(&mut self, time: T) -> Result<&[T], ModelError> {
+        if time.partial_cmp(&T::zero()).is_none() || !time.is_finite() {
+            return Err(ModelError::InvalidTime);
+        }
+        if time < self.time {
+            return Err(ModelError::NegativeTime);
+        }
+
+        while self.time < time {
+            let remaining = time.clone() - self.time.clone();
+            let (duration, next) = if self.step < remaining {
+                (self.step.clone(), self.time.clone() + self.step.clone())
+            } else {
+                (remaining, time.clone())
+            };
+
+            let volume = self.config.rain_rate.clone() * duration;
+            for column in 0..self.surface.len() {
+                pour(&mut self.surface, column, volume.clone(), &self.config.tolerance);
+            }
+            self.time = next;
+        }
+
+        Ok(&self.surface)
+    }
+}
+
+/// Flat run of the surface around the column, within the tolerance of its level
+fn flat_run<T: Scalar>(surface: &[T], column: usize, tolerance: &Tolerance<T>) -> (usize, usize) {
+    let level = &surface[column];
+    let start = (0..column).rev().take_while(|idx| tolerance.is_equal(&surface[*idx], level)).last().unwrap_or(column);
+    let end = (column + 1..surface.len()).take_while(|idx| tolerance.is_equal(&surface[*idx], level)).last().unwrap_or(column);
+    (start, end + 1)
+}
+
+/// Pour the volume of water onto the surface at the column
+///
+/// The water runs down to the lower neighbours of the flat run, halves to each if both are lower.
+/// A run without lower neighbours is a basin, it is raised up to its lower rim and the rest
+/// is poured again from there.
+fn pour<T: Scalar>(surface: &mut [T], column: usize, volume: T, tolerance: &Tolerance<T>) {
+    let (start, end) = flat_run(surface, column, tolerance);
+    let level = surface[column].clone();
+    let left = start.checked_sub(1);
+    let right = Some(end).filter(|idx| *idx < surface.len());
+    let is_lower = |idx: &usize| surface[*idx] < level;
+
+    match (left.filter(is_lower), right.filter(is_lower)) {
+        (Some(left), Some(right)) => {
+            let half = volume / T::from_usize(2);
+            pour(surface, left, half.clone(), tolerance);
+            pour(surface, right, half, tolerance);
+        }
+        (Some(lower), None) | (None, Some(lower)) => pour(surface, lower, volume, tolerance),
+        (None, None) => {
+            let width = T::from_usize(end - start);
+            let rim = left.into_iter()
+                .chain(right)
+                .map(|idx| surface[idx].clone())
+                .fold(None, |lowest: Option<T>, height| match lowest {
+                    Some(lowest) if lowest <= height => Some(lowest),
+                    _ => Some(height),
+                });
+
+            let needed = rim.clone().map(|rim| (rim - level.clone()) * width.clone());
+            match (rim, needed) {
+                (Some(rim), Some(needed)) if needed < volume => {
+                    for height in &mut surface[start..end] {
+                        *height = rim.clone();
+                    }
+                    pour(surface, column, volume - needed, tolerance);
+                }
+                _ => {
+                    let raised = level + volume / width;
+                    for height in &mut surface[start..end] {
+                        *height = raised.clone();
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Largest difference between the levels of the model and the reference simulation
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Deviation<T = f64> {
+    time: T,
+    column: usize,
+    error: T,
+}
+
+impl<T: Scalar> Deviation<T> {
+    /// Time of the largest difference
+    pub fn time(&self) -> T {
+        self.time.clone()
+    }
+
+    /// Column of the largest difference
+    pub fn column(&self) -> usize {
+        self.column
+    }
+
+    /// Absolute difference of the levels
+    pub fn error(&self) -> T {
+        self.error.clone()
+    }
+}
+
+impl<T: Scalar> Model<T> {
+    /// Create the reference simulation over the same terrain with the same options
+    pub fn reference(&self, step: T) -> Result<Reference<T>, ModelError> {
+        if !step.is_finite() || step <= T::zero() {
+            return Err(ModelError::InvalidStep);
+        }
+
+        Ok(Reference {
+            surface: self.ground_heights(),
+            time: T::zero(),
+            step,
+            config: self.config().clone(),
+        })
+    }
+
+    /// Compare the levels at the times with the ones of the reference simulation
+    ///
+    /// Returns the largest difference, the latest one for no times. The reference matches
+    /// the model up to rounding, while the water takes the same routes within each step.
+    /// A route changing within a step, e.g. a lake filling up to the top it shares with
+    /// a slope, misplaces a part of the rain of that step, so the difference shrinks with the step.
+    pub fn compare_with_reference(&self, times: &[T], step: T) -> Result<Deviation<T>, ModelError> {
+        let mut reference = self.reference(step.clone())?;
+
+        let mut times = times.to_vec();
+        for time in &times {
+            self.generation_at(time.clone())?;
+        }
+        times.sort_by(|a, b| a.partial_cmp(b).unwrap_or(core::cmp::Ordering::Equal));
+
+        let mut worst = Deviation {
+            time: T::zero(),
+            column: 0,
+            error: T::zero(),
+        };
+
+        for time in times {
+            let levels = self.calculate_levels(time.clone())?;
+            let expected = reference.advance_to(time.clone())?;
+
+            for (column, (level, expected)) in levels.into_iter().zip(expected).enumerate() {
+                let difference = level - expected.clone();
+                let error = if difference.is_negative() { T::zero() - difference } else { difference };
+                if error > worst.error {
+                    worst = Deviation {
+                        time: time.clone(),
+                        column,
+                        error,
+                    };
+                }
+            }
+        }
+
+        Ok(worst)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_abs_diff_eq;
+    use quickcheck::TestResult;
+    use quickcheck_macros::quickcheck;
+
+    use super::*;
+
+    const HEIGHTS: [f64; 6] = [3.0, 1.0, 6.0, 4.0, 8.0, 9.0];
+
+    #[test]
+    fn test_single_basin() {
+        let model = Model::new(&[2.0, 0.0, 2.0], 5.0).unwrap();
+        let mut reference = model.reference(0.1).unwrap();
+
+        // all the rain stays in the basin, until it is full at 2 / 3
+        assert_abs_diff_eq!(reference.advance_to(0.5).unwrap()[1], 1.5, epsilon = 1e-12);
+        assert_eq!(reference.levels()[0], 2.0);
+
+        // then the whole terrain rises as a single lake
+        let levels = reference.advance_to(1.0).unwrap().to_vec();
+        for level in levels {
+            assert_abs_diff_eq!(level, 7.0 / 3.0, epsilon = 1e-12);
+        }
+        assert_eq!(reference.time(), 1.0);
+    }
+
+    #[test]
+    fn test_spilling() {
+        let model = Model::new(&HEIGHTS, 5.0).unwrap();
+        let times = [0.3, 0.5, 4.0 / 7.0, 0.6, 2.0 / 3.0, 1.0, 2.0, 3.0, 5.0];
+
+        let coarse = model.compare_with_reference(&times, 0.01).unwrap();
+        let fine = model.compare_with_reference(&times, 0.001).unwrap();
+        // spills fill the basins below in the same way, so both are exact up to rounding
+        assert!(coarse.error() < 1e-9);
+        assert!(fine.error() < 1e-9);
+    }
+
+    #[test]
+    fn test_exact_between_merges() {
+        // no merges in the basin until it is full, so the reference is exact
+        let model = Model::new(&[5.0, 1.0, 0.0, 1.0, 5.0], 1.0).unwrap();
+        let deviation = model.compare_with_reference(&[0.1, 0.2], 0.05).unwrap();
+        assert!(deviation.error() < 1e-12);
+    }
+
+    #[test]
+    fn test_route_change_within_step() {
+        // the lakes fill up to the tops at 25.5 within a step, so the reference routes
+        // the rest of the rain of that step in the old way
+        let model = Model::new(&[0.0, 25.5, 23.3, 25.5, 24.9, 16.2], 3.0).unwrap();
+        let times = [0.99, 1.0, 3.0];
+
+        let coarse = model.compare_with_reference(&times, 0.01).unwrap();
+        let fine = model.compare_with_reference(&times, 0.001).unwrap();
+        assert!(coarse.error() > 1e-3);
+        assert!(fine.error() < coarse.error() / 5.0);
+    }
+
+    #[quickcheck]
+    fn converges(heights: Vec<u8>, time: u8) -> TestResult {
+        if heights.is_empty() || heights.len() > 12 {
+            return TestResult::discard();
+        }
+
+        let heights: Vec<f64> = heights.into_iter().map(|height| height as f64 / 10.0).collect();
+        let model = Model::new(&heights, 3.0).unwrap();
+        let times = [time as f64 / 100.0 % 3.0, 1.0, 3.0];
+
+        TestResult::from_bool(model.compare_with_reference(&times, 0.0005).unwrap().error() < 1e-2)
+    }
+
+    #[test]
+    fn test_errors() {
+        let model = Model::new(&HEIGHTS, 1.0).unwrap();
+
+        assert!(matches!(model.reference(0.0), Err(ModelError::InvalidStep)));
+        assert!(matches!(model.reference(f64::NAN), Err(ModelError::InvalidStep)));
+        assert_eq!(model.compare_with_reference(&[2.0], 0.1), Err(ModelError::TimeOutOfHorizon));
+
+        let mut reference = model.reference(0.1).unwrap();
+        reference.advance_to(0.5).unwrap();
+        assert_eq!(reference.advance_to(0.2), Err(ModelError::NegativeTime));
+        assert_eq!(reference.advance_to(f64::INFINITY), Err(ModelError::InvalidTime));
+    }
+}
@@ -0,0 +1,184 @@
+use alloc::vec::Vec;
+use core::cmp::Ordering;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::catchment::Catchment;
+use crate::error::ModelError;
+use crate::model::Model;
+use crate::scalar::Scalar;
+
+/// Row of the flood risk table
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct FloodRisk<T = f64> {
+    column: usize,
+    arrival_time: Option<T>,
+    max_depth: T,
+    catchment_size: T,
+    score: T,
+}
+
+impl<T: Scalar> FloodRisk<T> {
+    pub fn column(&self) -> usize {
+        self.column
+    }
+
+    /// Time when the column first gets wet, see `Model::arrival_times`
+    pub fn arrival_time(&self) -> Option<T> {
+        self.arrival_time.clone()
+    }
+
+    /// Depth of the water at the max time, levels only rise, so it is the maximum depth
+    pub fn max_depth(&self) -> T {
+        self.max_depth.clone()
+    }
+
+    /// Number of columns draining into the basin, where the rain on the column ends up,
+    /// in the initial terrain. Split columns get the larger of their two basins.
+    pub fn catchment_size(&self) -> T {
+        self.catchment_size.clone()
+    }
+
+    /// Combined risk between 0 and 1, the average of the normalized factors
+    pub fn score(&self) -> T {
+        self.score.clone()
+    }
+}
+
+fn max<T: Scalar>(values: &[T]) -> T {
+    values.iter().fold(T::zero(), |acc, value| if *value > acc { value.clone() } else { acc })
+}
+
+/// Value divided by the maximum, zero when all the values are zero
+fn normalize<T: Scalar>(value: &T, max: &T) -> T {
+    if *max > T::zero() {
+        value.clone() / max.clone()
+    } else {
+        T::zero()
+    }
+}
+
+impl<T: Scalar> Model<T> {
+    /// Rank the columns by flood risk, the riskiest first
+    ///
+    /// Combines how early the column gets wet relative to the max time, the maximum
+    /// depth and the size of the catchment, each factor is normalized to 0..1 and weighted equally.
+    /// Columns staying dry within the max time score zero, whatever their catchment.
+    /// Columns with the same score are ordered by their index.
+    pub fn flood_risk(&self) -> Result<Vec<FloodRisk<T>>, ModelError> {
+        let max_time = self.max_time().clone();
+        let ground = self.ground_heights();
+        let levels = self.calculate_levels(max_time.clone())?;
+        let arrivals = self.arrival_times();
+        let catchments = self.catchments_at(T::zero())?;
+
+        let depths: Vec<T> = levels.into_iter().zip(ground).map(|(level, ground)| level - ground).collect();
+        let sizes: Vec<T> = catchments.labels()
+            .iter()
+            .map(|label| {
+                let size = |basin: &usize| catchments.sizes().get(*basin).cloned().unwrap_or_else(T::zero);
+                match label {
+                    Catchment::Basin(basin) => size(basin),
+                    Catchment::Split(left, right) => max(&[size(left), size(right)]),
+                    Catchment::None => T::zero(),
+                }
+            })
+            .collect();
+
+        let max_depth = max(&depths);
+        let max_size = max(&sizes);
+        let one = T::from_usize(1);
+        let three = T::from_usize(3);
+
+        let mut table: Vec<FloodRisk<T>> = arrivals.into_iter()
+            .zip(depths.into_iter().zip(sizes))
+            .enumerate()
+            .map(|(column, (arrival_time, (max_depth_at, catchment_size)))| {
+                let earliness = match &arrival_time {
+                    Some(time) if max_time > T::zero() => Some(one.clone() - time.clone() / max_time.clone()),
+                    Some(_) => Some(one.clone()),
+                    None => None,
+                };
+                // the catchment only matters for the columns, where water stands
+                let score = match earliness {
+                    Some(earliness) => {
+                        (earliness + normalize(&max_depth_at, &max_depth) + normalize(&catchment_size, &max_size)) / three.clone()
+                    }
+                    None => T::zero(),
+                };
+
+                FloodRisk {
+                    column,
+                    arrival_time,
+                    max_depth: max_depth_at,
+                    catchment_size,
+                    score,
+                }
+            })
+            .collect();
+
+        table.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap_or(Ordering::Equal));
+
+        Ok(table)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_flood_risk() {
+        let model = Model::new(&[3.0, 1.0, 6.0, 4.0, 8.0, 9.0], 2.0).unwrap();
+        let table = model.flood_risk().unwrap();
+
+        assert_eq!(table.len(), 6);
+        for pair in table.windows(2) {
+            assert!(pair[0].score() >= pair[1].score());
+        }
+
+        // the left basin gets wet right away and is the deepest
+        let riskiest = &table[0];
+        assert_eq!(riskiest.column(), 1);
+        assert_eq!(riskiest.arrival_time(), Some(0.0));
+        assert_eq!(riskiest.catchment_size(), 2.5);
+
+        // the peaks stay dry, so their large catchment does not matter
+        let safest = table.last().unwrap();
+        assert_eq!(safest.column(), 5);
+        assert_eq!(safest.arrival_time(), None);
+        assert_eq!(safest.max_depth(), 0.0);
+        assert_eq!(safest.catchment_size(), 3.5);
+        assert_eq!(safest.score(), 0.0);
+        assert_eq!(table[4].column(), 4);
+        assert_eq!(table[4].score(), 0.0);
+    }
+
+    #[test]
+    fn test_dry_ridge() {
+        // the ridge drains the wide field on the right, the slopes of the small basin
+        // get covered at 6 / 7, just before the max time, and stay shallow
+        let model = Model::new(&[3.0, 0.0, 3.0, 20.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0], 0.9).unwrap();
+        let table = model.flood_risk().unwrap();
+
+        let rank = |column: usize| table.iter().position(|row| row.column() == column).unwrap();
+        let (ridge, slope) = (&table[rank(3)], &table[rank(0)]);
+        assert!(slope.arrival_time().is_some());
+        assert!(ridge.catchment_size() > slope.catchment_size());
+        assert_eq!(ridge.score(), 0.0);
+        assert!(slope.score() > 0.0);
+        assert!(rank(0) < rank(3));
+    }
+
+    #[test]
+    fn test_flat() {
+        let model = Model::new(&[1.0, 1.0], 0.0).unwrap();
+        let table = model.flood_risk().unwrap();
+
+        assert_eq!(table.iter().map(|row| row.column()).collect::<Vec<_>>(), vec![0, 1]);
+        assert_eq!(table[0].max_depth(), 0.0);
+        assert_eq!(table[0].score(), 2.0 / 3.0);
+    }
+}
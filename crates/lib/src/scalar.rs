@@ -0,0 +1,66 @@
+use core::fmt::Debug;
+use core::ops::{Add, Div, Mul, Sub};
+
+/// Numeric type used for heights and times in the model
+///
+/// Implemented for `f32` and `f64`. Custom scalars (e.g. higher-precision
+/// or exact types) may be plugged in by implementing this trait.
+pub trait Scalar:
+    Clone
+    + Debug
+    + PartialOrd
+    + Add<Output = Self>
+    + Sub<Output = Self>
+    + Mul<Output = Self>
+    + Div<Output = Self>
+{
+    fn zero() -> Self;
+
+    fn from_usize(v: usize) -> Self;
+
+    /// Returns true for values which are neither infinite nor NaN
+    fn is_finite(&self) -> bool;
+
+    /// Returns true for values below zero
+    fn is_negative(&self) -> bool;
+
+    /// Nearest `f64` value, used for reporting
+    fn to_f64(&self) -> f64;
+
+    /// Absolute tolerance used to check if two values should be considered equal,
+    /// unless configured explicitly
+    fn default_tolerance() -> Self;
+}
+
+macro_rules! impl_float_scalar {
+    ($t:ty) => {
+        impl Scalar for $t {
+            fn zero() -> Self {
+                0.0
+            }
+
+            fn from_usize(v: usize) -> Self {
+                v as $t
+            }
+
+            fn is_finite(&self) -> bool {
+                <$t>::is_finite(*self)
+            }
+
+            fn is_negative(&self) -> bool {
+                self.is_sign_negative()
+            }
+
+            fn to_f64(&self) -> f64 {
+                *self as f64
+            }
+
+            fn default_tolerance() -> Self {
+                <$t>::EPSILON
+            }
+        }
+    };
+}
+
+impl_float_scalar!(f32);
+impl_float_scalar!(f64);
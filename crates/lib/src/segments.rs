@@ -0,0 +1,69 @@
+use alloc::vec::Vec;
+use core::iter;
+
+use crate::model::Model;
+use crate::scalar::Scalar;
+
+/// Piece of the level of a column within a generation, where it rises linearly
+pub(crate) struct Segment<T> {
+    pub(crate) start: T,
+
+    /// the last segment has no end
+    pub(crate) end: Option<T>,
+
+    /// level at the start of the segment
+    pub(crate) level: T,
+
+    /// rise of the level per unit of time
+    pub(crate) rate: T,
+}
+
+impl<T: Scalar> Segment<T> {
+    pub(crate) fn level_at(&self, time: &T) -> T {
+        self.level.clone() + self.rate.clone() * (time.clone() - self.start.clone())
+    }
+
+    /// Earliest time within the segment, when the level reaches the target
+    pub(crate) fn time_of_level(&self, target: &T) -> Option<T> {
+        if self.level >= *target {
+            return Some(self.start.clone());
+        }
+
+        if self.rate <= T::zero() {
+            return None;
+        }
+
+        let time = self.start.clone() + (target.clone() - self.level.clone()) / self.rate.clone();
+        match &self.end {
+            Some(end) if time > *end => None,
+            _ => Some(time),
+        }
+    }
+}
+
+impl<T: Scalar> Model<T> {
+    /// Level of the column in each generation, including ones after the max time
+    pub(crate) fn column_segments(&self, column: usize) -> impl Iterator<Item = Segment<T>> + '_ {
+        self.generations().iter().filter_map(move |generation| {
+            let parts = generation.parts.as_ref();
+            let idx = parts.partition_point(|part| part.range().end <= column);
+            let (part, (velocity, num_columns)) = parts.get(idx).zip(generation.parts.velocities().get(idx))?;
+
+            Some(Segment {
+                start: generation.start.clone(),
+                end: generation.end.clone(),
+                level: part.height(),
+                rate: velocity.clone() / T::from_usize(*num_columns),
+            })
+        })
+    }
+
+    /// Height of the ground at each column before any water was added
+    pub(crate) fn ground_heights(&self) -> Vec<T> {
+        self.initial_parts()
+            .as_ref()
+            .iter()
+            .flat_map(|part| iter::repeat_n(part.height(), part.range().len()))
+            .collect()
+    }
+}
@@ -0,0 +1,413 @@
+use alloc::vec;
+use alloc::vec::Vec;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::error::ModelError;
+use crate::model::{Generation, Model};
+use crate::scalar::Scalar;
+use crate::tolerance::Tolerance;
+
+/// Derivatives of the levels of all columns at some time
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Derivatives<T = f64> {
+    heights: Vec<Vec<T>>,
+    rain_rate: Vec<T>,
+}
+
+impl<T: Scalar> Derivatives<T> {
+    /// Derivative of each column level with respect to each initial height,
+    /// indexed as `[column][height]`
+    pub fn heights(&self) -> &[Vec<T>] {
+        &self.heights
+    }
+
+    /// Derivative of each column level with respect to the rain rate
+    pub fn rain_rate(&self) -> &[T] {
+        &self.rain_rate
+    }
+}
+
+/// Sensitivity of the levels to the terrain heights and to the rain rate
+///
+/// Within a generation levels are affine in the initial heights. At the time of a merge
+/// levels are still continuous, but their derivatives jump, so both one-sided
+/// derivatives are reported.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Sensitivity<T = f64> {
+    Smooth(Derivatives<T>),
+
+    AtMerge {
+        /// Derivatives before the merge, i.e. as if the merge happened later
+        before: Derivatives<T>,
+
+        /// Derivatives after the merge, i.e. as if the merge happened earlier
+        after: Derivatives<T>,
+    },
+}
+
+impl<T: Scalar> Sensitivity<T> {
+    /// Derivatives after the merge, if it happens at the time
+    pub fn after(&self) -> &Derivatives<T> {
+        match self {
+            Sensitivity::Smooth(derivatives) => derivatives,
+            Sensitivity::AtMerge { after, .. } => after,
+        }
+    }
+}
+
+/// Derivatives of the start time and of the part heights at the start of a generation
+///
+/// Each vector is indexed by the input, initial heights go first and the rain rate is the last
+struct State<T> {
+    start: Vec<T>,
+    heights: Vec<Vec<T>>,
+}
+
+/// Derivatives of the velocities of the parts divided by the number of their columns
+///
+/// Velocities depend only on the rain rate
+fn rate_derivatives<T: Scalar>(generation: &Generation<T>, num_inputs: usize) -> Vec<Vec<T>> {
+    generation.parts.unit_velocities()
+        .into_iter()
+        .map(|(unit_velocity, num_columns)| {
+            let mut derivatives = vec![T::zero(); num_inputs];
+            if let Some(last) = derivatives.last_mut() {
+                *last = unit_velocity / T::from_usize(num_columns);
+            }
+            derivatives
+        })
+        .collect()
+}
+
+fn rates<T: Scalar>(generation: &Generation<T>) -> Vec<T> {
+    generation.parts.velocities()
+        .iter()
+        .map(|(velocity, num_columns)| velocity.clone() / T::from_usize(*num_columns))
+        .collect()
+}
+
+fn initial_state<T: Scalar>(generation: &Generation<T>, num_inputs: usize) -> State<T> {
+    let heights = generation.parts.as_ref()
+        .iter()
+        .map(|part| {
+            // columns of the part were merged, so they all share the same level
+            let share = T::from_usize(1) / T::from_usize(part.range().len());
+            let mut derivatives = vec![T::zero(); num_inputs];
+            for column in part.range() {
+                derivatives[column] = share.clone();
+            }
+            derivatives
+        })
+        .collect();
+
+    State {
+        start: vec![T::zero(); num_inputs],
+        heights,
+    }
+}
+
+/// Derivatives of the levels of the parts of the generation at the time
+fn part_derivatives<T: Scalar>(generation: &Generation<T>, state: &State<T>, time: &T) -> Vec<Vec<T>> {
+    let offset = time.clone() - generation.start.clone();
+    let rates = rates(generation);
+    let rate_derivatives = rate_derivatives(generation, state.start.len());
+
+    state.heights.iter()
+        .zip(rates.iter().zip(rate_derivatives.iter()))
+        .map(|(heights, (rate, rate_derivatives))| {
+            heights.iter()
+                .zip(rate_derivatives.iter().zip(state.start.iter()))
+                .map(|(height, (rate_derivative, start))| {
+                    height.clone() + rate_derivative.clone() * offset.clone() - rate.clone() * start.clone()
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Propagate the derivatives through the configuration change at the end of the generation
+fn advance<T: Scalar>(generation: &Generation<T>, next: &Generation<T>, state: &State<T>, tolerance: &Tolerance<T>) -> Result<State<T>, ModelError> {
+    let parts = generation.parts.as_ref();
+    let end = generation.end.clone().ok_or(ModelError::Inconsistency("generation has no end"))?;
+    let duration = end - generation.start.clone();
+
+    let changes = generation.parts.next_change()
+        .as_ref()
+        .map(|(changes, _)| changes.as_slice())
+        .filter(|changes| !changes.is_empty())
+        .ok_or(ModelError::Inconsistency("generation has no changes"))?;
+
+    let rates = rates(generation);
+    let rate_derivatives = rate_derivatives(generation, state.start.len());
+
+    // each change happens, when the levels of the changed part and of the reached one become equal
+    let mut end_derivatives: Option<Vec<T>> = None;
+    for (changed, target) in changes {
+        let changed = *changed;
+        let reached = generation.parts.reached_neighbour(changed, target, tolerance)
+            .ok_or(ModelError::Inconsistency("changed part has no neighbours"))?;
+
+        let (changed_rate, reached_rate) = match (rates.get(changed), rates.get(reached)) {
+            (Some(changed_rate), Some(reached_rate)) => (changed_rate.clone(), reached_rate.clone()),
+            _ => return Err(ModelError::Inconsistency("changed part is missing")),
+        };
+        let closing_rate = changed_rate - reached_rate;
+        if closing_rate <= T::zero() {
+            return Err(ModelError::Inconsistency("changed part does not reach its neighbour"));
+        }
+
+        let derivatives: Vec<T> = (0..state.start.len())
+            .map(|k| {
+                let gap = state.heights[reached][k].clone() - state.heights[changed][k].clone();
+                let closing = rate_derivatives[changed][k].clone() - rate_derivatives[reached][k].clone();
+                state.start[k].clone() + (gap - duration.clone() * closing) / closing_rate.clone()
+            })
+            .collect();
+
+        // simultaneous changes drift apart differently, when the inputs change,
+        // so the order of the merges and the levels after them depend on the direction
+        match &end_derivatives {
+            Some(first) if !first.iter().zip(&derivatives).all(|(a, b)| tolerance.is_equal(a, b)) => {
+                return Err(ModelError::NonDifferentiable);
+            }
+            Some(_) => {}
+            None => end_derivatives = Some(derivatives),
+        }
+    }
+    let end_derivatives = end_derivatives.ok_or(ModelError::Inconsistency("generation has no changes"))?;
+
+    // total derivatives of the levels at the time of the change
+    let at_change: Vec<Vec<T>> = state.heights.iter()
+        .zip(rates.iter().zip(rate_derivatives.iter()))
+        .map(|(heights, (rate, rate_derivatives))| {
+            (0..heights.len())
+                .map(|k| {
+                    heights[k].clone()
+                        + rate_derivatives[k].clone() * duration.clone()
+                        + rate.clone() * (end_derivatives[k].clone() - state.start[k].clone())
+                })
+                .collect()
+        })
+        .collect();
+
+    // merged parts get the volume weighted derivatives of the parts they consist of
+    let mut heights = Vec::with_capacity(next.parts.as_ref().len());
+    let mut idx = 0;
+    for part in next.parts.as_ref() {
+        let range = part.range();
+        let mut derivatives = vec![T::zero(); state.start.len()];
+        while idx < parts.len() && parts[idx].range().end <= range.end {
+            let share = T::from_usize(parts[idx].range().len()) / T::from_usize(range.len());
+            for (derivative, old) in derivatives.iter_mut().zip(at_change[idx].iter()) {
+                *derivative = derivative.clone() + share.clone() * old.clone();
+            }
+            idx += 1;
+        }
+        heights.push(derivatives);
+    }
+
+    Ok(State {
+        start: end_derivatives,
+        heights,
+    })
+}
+
+fn column_derivatives<T: Scalar>(generation: &Generation<T>, part_derivatives: Vec<Vec<T>>, num_columns: usize) -> Derivatives<T> {
+    let mut heights = Vec::with_capacity(num_columns);
+    let mut rain_rate = Vec::with_capacity(num_columns);
+
+    for (part, mut derivatives) in generation.parts.as_ref().iter().zip(part_derivatives) {
+        let rain = derivatives.pop().unwrap_or_else(T::zero);
+        for _ in part.range() {
+            heights.push(derivatives.clone());
+            rain_rate.push(rain.clone());
+        }
+    }
+
+    Derivatives { heights, rain_rate }
+}
+
+impl<T: Scalar> Model<T> {
+    /// Calculate derivatives of `calculate_levels(time)` with respect to each
+    /// initial height and to the rain rate
+    ///
+    /// Columns, which were merged in the initial terrain because of the tolerance,
+    /// share the derivative equally. Times within the time tolerance of a merge on either side
+    /// are at the merge. Fails with `NonDifferentiable` after simultaneous merges,
+    /// which drift apart differently with the inputs.
+    pub fn sensitivity(&self, time: T) -> Result<Sensitivity<T>, ModelError> {
+        // validate the time
+        self.generation_at(time.clone())?;
+
+        let generations = self.generations();
+        let tolerance = &self.config().tolerance;
+        let time_tolerance = &self.config().time_tolerance;
+        let num_columns = self.initial_parts().num_columns();
+        let num_inputs = num_columns + 1;
+
+        let mut current = generations.partition_point(|generation| generation.start <= time).saturating_sub(1);
+        // the time just before a merge within the tolerance is at the merge as well
+        if generations.get(current + 1).is_some_and(|next| time_tolerance.is_equal(&time, &next.start)) {
+            current += 1;
+        }
+        let first = generations.first().ok_or(ModelError::Inconsistency("no generations"))?;
+        let mut states = Vec::with_capacity(current + 1);
+        states.push(initial_state(first, num_inputs));
+        for idx in 0..current {
+            let state = states.pop().ok_or(ModelError::Inconsistency("no state"))?;
+            let next = advance(&generations[idx], &generations[idx + 1], &state, tolerance)?;
+            // the state before the last merge is kept for the derivatives before it
+            if idx + 1 == current {
+                states.push(state);
+            }
+            states.push(next);
+        }
+
+        let generation = &generations[current];
+        let state = states.last().ok_or(ModelError::Inconsistency("no state"))?;
+        let after = column_derivatives(generation, part_derivatives(generation, state, &time), num_columns);
+
+        if current == 0 || !time_tolerance.is_equal(&time, &generation.start) {
+            return Ok(Sensitivity::Smooth(after));
+        }
+
+        // derivatives of the previous generation extended up to the time of the merge
+        let before_generation = &generations[current - 1];
+        let before = column_derivatives(before_generation, part_derivatives(before_generation, &states[0], &time), num_columns);
+
+        Ok(Sensitivity::AtMerge { before, after })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_abs_diff_eq;
+
+    use crate::ModelBuilder;
+
+    use super::*;
+
+    const HEIGHTS: [f64; 8] = [3.0, 1.0, 6.0, 4.0, 8.0, 9.0, 2.5, 5.0];
+
+    fn levels(heights: &[f64], rain_rate: f64, time: f64) -> Vec<f64> {
+        ModelBuilder::new()
+            .heights(heights)
+            .max_time(100.0)
+            .rain_rate(rain_rate)
+            .build()
+            .unwrap()
+            .calculate_levels(time)
+            .unwrap()
+    }
+
+    fn assert_matches_finite_differences(time: f64, derivatives: &Derivatives<f64>, epsilon: f64) {
+        let eps = 1e-6;
+
+        for k in 0..HEIGHTS.len() {
+            let mut up = HEIGHTS;
+            up[k] += eps;
+            let mut down = HEIGHTS;
+            down[k] -= eps;
+
+            let up = levels(&up, 1.0, time);
+            let down = levels(&down, 1.0, time);
+            for column in 0..HEIGHTS.len() {
+                let expected = (up[column] - down[column]) / (2.0 * eps);
+                assert_abs_diff_eq!(derivatives.heights()[column][k], expected, epsilon = epsilon);
+            }
+        }
+
+        let up = levels(&HEIGHTS, 1.0 + eps, time);
+        let down = levels(&HEIGHTS, 1.0 - eps, time);
+        for column in 0..HEIGHTS.len() {
+            let expected = (up[column] - down[column]) / (2.0 * eps);
+            assert_abs_diff_eq!(derivatives.rain_rate()[column], expected, epsilon = epsilon);
+        }
+    }
+
+    fn assert_close(derivatives: &Derivatives<f64>, expected: &Derivatives<f64>) {
+        for (column, heights) in derivatives.heights().iter().enumerate() {
+            for (k, derivative) in heights.iter().enumerate() {
+                assert_abs_diff_eq!(*derivative, expected.heights()[column][k], epsilon = 1e-7);
+            }
+            assert_abs_diff_eq!(derivatives.rain_rate()[column], expected.rain_rate()[column], epsilon = 1e-7);
+        }
+    }
+
+    #[test]
+    fn test_first_generation() {
+        let model = Model::new(&HEIGHTS, 100.0).unwrap();
+        let sensitivity = model.sensitivity(0.1).unwrap();
+        let derivatives = match &sensitivity {
+            Sensitivity::Smooth(derivatives) => derivatives,
+            _ => panic!("unexpected merge"),
+        };
+
+        assert_abs_diff_eq!(derivatives.heights()[1][1], 1.0);
+        assert_abs_diff_eq!(derivatives.heights()[1][0], 0.0);
+        assert_abs_diff_eq!(derivatives.rain_rate()[1], 0.25);
+
+        assert_matches_finite_differences(0.1, derivatives, 1e-5);
+    }
+
+    #[test]
+    fn test_after_merges() {
+        let model = Model::new(&HEIGHTS, 100.0).unwrap();
+        let events: Vec<f64> = model.generations().iter().map(|generation| generation.start).collect();
+
+        for pair in events.windows(2) {
+            let time = (pair[0] + pair[1]) / 2.0;
+            match model.sensitivity(time).unwrap() {
+                Sensitivity::Smooth(derivatives) => assert_matches_finite_differences(time, &derivatives, 1e-5),
+                _ => panic!("unexpected merge at {}", time),
+            }
+        }
+
+        let last = *events.last().unwrap() + 1.0;
+        assert_matches_finite_differences(last, model.sensitivity(last).unwrap().after(), 1e-5);
+    }
+
+    #[test]
+    fn test_at_merge() {
+        let model = Model::new(&HEIGHTS, 100.0).unwrap();
+        let merge_time = model.generations()[1].start;
+
+        let (before, after) = match model.sensitivity(merge_time).unwrap() {
+            Sensitivity::AtMerge { before, after } => (before, after),
+            _ => panic!("expected merge"),
+        };
+        assert_ne!(before, after);
+
+        // one-sided derivatives continue the derivatives of the generations around the merge
+        for (time, expected) in [(merge_time - 1e-9, &before), (merge_time + 1e-9, &after)] {
+            let derivatives = match model.sensitivity(time).unwrap() {
+                Sensitivity::Smooth(derivatives) => derivatives,
+                _ => panic!("unexpected merge at {}", time),
+            };
+            assert_close(&derivatives, expected);
+        }
+
+        // a time just before the merge within the tolerance is at the merge as well
+        let just_before = merge_time - f64::EPSILON / 2.0;
+        assert!(just_before < merge_time);
+        match model.sensitivity(just_before).unwrap() {
+            Sensitivity::AtMerge { before: just_before, after: just_after } => {
+                assert_close(&just_before, &before);
+                assert_close(&just_after, &after);
+            }
+            _ => panic!("expected merge"),
+        }
+    }
+
+    #[test]
+    fn test_simultaneous_merges() {
+        // both basins fill up to the walls at 0.4
+        let model = Model::new(&[2.0, 1.0, 2.0, 1.0, 2.0], 10.0).unwrap();
+        assert!(matches!(model.sensitivity(0.2).unwrap(), Sensitivity::Smooth(_)));
+        assert_eq!(model.sensitivity(0.5), Err(ModelError::NonDifferentiable));
+    }
+}
@@ -0,0 +1,151 @@
+use alloc::vec::Vec;
+use core::ops::Range;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::model::Model;
+use crate::scalar::Scalar;
+
+/// Basin filled up to its lowest rim and overflowing it
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Spill<T = f64> {
+    basin: Range<usize>,
+    spill_time: T,
+    spill_level: T,
+    rim: Range<usize>,
+    left: Option<Range<usize>>,
+    right: Option<Range<usize>>,
+}
+
+impl<T: Scalar> Spill<T> {
+    /// Columns of the basin before the spill
+    pub fn basin(&self) -> Range<usize> {
+        self.basin.clone()
+    }
+
+    /// Time when the basin reaches its rim
+    pub fn spill_time(&self) -> T {
+        self.spill_time.clone()
+    }
+
+    /// Level of the lowest rim
+    pub fn spill_level(&self) -> T {
+        self.spill_level.clone()
+    }
+
+    /// Columns of the neighbouring part forming the lowest rim
+    pub fn rim(&self) -> Range<usize> {
+        self.rim.clone()
+    }
+
+    /// Columns of the neighbouring lake receiving the water on the left, see `LakeState::Overflowing`
+    pub fn left(&self) -> Option<Range<usize>> {
+        self.left.clone()
+    }
+
+    /// Columns of the neighbouring lake receiving the water on the right
+    pub fn right(&self) -> Option<Range<usize>> {
+        self.right.clone()
+    }
+}
+
+impl<T: Scalar> Model<T> {
+    /// Find all the basins filling up to their lowest rim and overflowing it, ordered by spill time
+    ///
+    /// Basins reaching their rims at the same time are ordered by their columns.
+    /// Includes the spills after the max time of the model. Basins covering a slope
+    /// or joining another lake at the level of the rim keep filling, so they are not spills.
+    pub fn spills(&self) -> Vec<Spill<T>> {
+        let mut spills = Vec::new();
+
+        for pair in self.generations().windows(2) {
+            let (generation, next) = (&pair[0], &pair[1]);
+            let (changes, end) = match (generation.parts.next_change(), &generation.end) {
+                (Some((changes, _)), Some(end)) => (changes, end),
+                _ => continue,
+            };
+            let parts = generation.parts.as_ref();
+            let next_parts = next.parts.as_ref();
+
+            for (idx, level) in changes {
+                if !generation.parts.accepts_water(*idx) {
+                    continue;
+                }
+
+                let rim = match generation.parts.reached_neighbour(*idx, level, &self.config().tolerance) {
+                    Some(rim) => rim,
+                    None => continue,
+                };
+                let (basin, rim) = match (parts.get(*idx), parts.get(rim)) {
+                    (Some(basin), Some(rim)) => (basin.range(), rim.range()),
+                    _ => continue,
+                };
+
+                let joined = next_parts.partition_point(|part| part.range().end <= basin.start);
+                if next.parts.accepts_water(joined) {
+                    continue;
+                }
+
+                let (left, right) = next.parts.runoff_destinations(joined);
+                spills.push(Spill {
+                    basin,
+                    spill_time: end.clone(),
+                    spill_level: level.clone(),
+                    rim,
+                    left: left.and_then(|left| next_parts.get(left)).map(|part| part.range()),
+                    right: right.and_then(|right| next_parts.get(right)).map(|part| part.range()),
+                });
+            }
+        }
+
+        spills
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_abs_diff_eq;
+
+    use super::*;
+
+    #[test]
+    fn test_spills() {
+        let model = Model::new(&[3.0, 1.0, 6.0, 4.0, 8.0, 9.0], 20.0).unwrap();
+        let spills = model.spills();
+
+        // the right basin spills over the wall at 6.0 into the left one
+        let first = &spills[0];
+        assert_eq!(first.basin(), 3..4);
+        assert_abs_diff_eq!(first.spill_time(), 2.0 / 3.5);
+        assert_eq!(first.spill_level(), 6.0);
+        assert_eq!(first.rim(), 2..3);
+
+        assert_eq!(first.left(), Some(1..2));
+        assert_eq!(first.right(), None);
+
+        // the left basin covering the slope at 3.0 and joining the right lake keeps filling
+        assert_eq!(spills.len(), 1);
+    }
+
+    #[test]
+    fn test_spill_sequence() {
+        let model = Model::new(&[9.0, 2.0, 5.0, 0.0, 4.0, 1.0, 9.0], 20.0).unwrap();
+        let spills = model.spills();
+
+        // both side basins spill into the middle one at the same time, ordered by the columns
+        assert_eq!(spills.len(), 2);
+        assert_eq!(spills[0].basin(), 1..2);
+        assert_eq!(spills[0].right(), Some(3..4));
+        assert_eq!(spills[1].basin(), 5..6);
+        assert_eq!(spills[1].left(), Some(3..4));
+        assert_abs_diff_eq!(spills[0].spill_time(), spills[1].spill_time());
+    }
+
+    #[test]
+    fn test_flat() {
+        let model = Model::new(&[1.0, 1.0, 1.0], 20.0).unwrap();
+        assert!(model.spills().is_empty());
+    }
+}
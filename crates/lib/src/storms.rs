@@ -0,0 +1,450 @@
+use alloc::vec::Vec;
+
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+use rand_distr::{Distribution, Exp};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::error::ModelError;
+use crate::model::Model;
+
+/// Period of constant rain
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Storm {
+    start: f64,
+    duration: f64,
+    intensity: f64,
+}
+
+impl Storm {
+    pub fn new(start: f64, duration: f64, intensity: f64) -> Self {
+        Storm { start, duration, intensity }
+    }
+
+    pub fn start(&self) -> f64 {
+        self.start
+    }
+
+    pub fn duration(&self) -> f64 {
+        self.duration
+    }
+
+    pub fn end(&self) -> f64 {
+        self.start + self.duration
+    }
+
+    /// Amount of water falling on each column per unit of time during the storm
+    pub fn intensity(&self) -> f64 {
+        self.intensity
+    }
+
+    /// Amount of water falling on each column by the time
+    pub fn rain_until(&self, time: f64) -> f64 {
+        (time.min(self.end()) - self.start).max(0.0) * self.intensity
+    }
+}
+
+/// Summary of the storms within the horizon of the sequence
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct StormStatistics {
+    storms: usize,
+    total_rain: f64,
+    max_intensity: f64,
+    mean_duration: f64,
+    wet_fraction: f64,
+}
+
+impl StormStatistics {
+    pub fn storms(&self) -> usize {
+        self.storms
+    }
+
+    /// Amount of water falling on each column within the horizon
+    pub fn total_rain(&self) -> f64 {
+        self.total_rain
+    }
+
+    /// Highest rain rate, overlapping storms add up
+    pub fn max_intensity(&self) -> f64 {
+        self.max_intensity
+    }
+
+    pub fn mean_duration(&self) -> f64 {
+        self.mean_duration
+    }
+
+    /// Part of the horizon, when it rains
+    pub fn wet_fraction(&self) -> f64 {
+        self.wet_fraction
+    }
+}
+
+/// Storms ordered by their start within the horizon
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct StormSequence {
+    storms: Vec<Storm>,
+    horizon: f64,
+}
+
+impl StormSequence {
+    /// Create the sequence from the storms, which may overlap
+    pub fn new(mut storms: Vec<Storm>, horizon: f64) -> Result<Self, ModelError> {
+        if !is_positive_number(horizon) {
+            return Err(ModelError::InvalidStormParameters);
+        }
+
+        let is_valid = |storm: &Storm| {
+            is_positive_number(storm.start) && is_positive_number(storm.duration) && is_positive_number(storm.intensity)
+        };
+        if !storms.iter().all(is_valid) {
+            return Err(ModelError::InvalidStormParameters);
+        }
+
+        storms.sort_by(|a, b| a.start.total_cmp(&b.start));
+
+        Ok(StormSequence { storms, horizon })
+    }
+
+    pub fn storms(&self) -> &[Storm] {
+        &self.storms
+    }
+
+    pub fn horizon(&self) -> f64 {
+        self.horizon
+    }
+
+    /// Amount of water falling on each column by the time
+    pub fn rain_until(&self, time: f64) -> f64 {
+        self.storms.iter()
+            .take_while(|storm| storm.start < time)
+            // summing from 0.0 rather than -0.0, which is negative for the model
+            .fold(0.0, |acc, storm| acc + storm.rain_until(time))
+    }
+
+    /// Times, when the rain rate changes, with the rate after them and the rain fallen by them
+    ///
+    /// Built in one sweep over the starts and ends of the storms. The rate is reset to exactly
+    /// zero, when no storm is active, rather than left with the residue of adding and
+    /// subtracting intensities.
+    fn rain_curve(&self) -> RainCurve {
+        let mut events: Vec<(f64, f64, isize)> = self.storms.iter()
+            .flat_map(|storm| [(storm.start, storm.intensity, 1), (storm.end(), -storm.intensity, -1)])
+            .collect();
+        // the starts go first, so the count of the active storms never drops below zero
+        events.sort_by(|a, b| a.0.total_cmp(&b.0).then(b.2.cmp(&a.2)));
+
+        let mut curve = RainCurve::default();
+        let mut active = 0;
+        let mut rate = 0.0;
+        for (time, change, count) in events {
+            active += count;
+            rate = if active == 0 { 0.0 } else { rate + change };
+
+            if curve.times.last() == Some(&time) {
+                if let Some(last) = curve.rates.last_mut() {
+                    *last = rate;
+                }
+                continue;
+            }
+
+            let fallen = match (curve.times.last(), curve.rates.last(), curve.fallen.last()) {
+                (Some(previous), Some(previous_rate), Some(fallen)) => fallen + (time - previous) * previous_rate,
+                _ => 0.0,
+            };
+            curve.times.push(time);
+            curve.rates.push(rate);
+            curve.fallen.push(fallen);
+        }
+
+        curve
+    }
+
+    /// Earliest time by which the amount of water falls on each column, None if it never does
+    pub fn time_of_rain(&self, amount: f64) -> Option<f64> {
+        self.rain_curve().time_of_rain(amount)
+    }
+
+    pub fn statistics(&self) -> StormStatistics {
+        let within: Vec<&Storm> = self.storms.iter().filter(|storm| storm.start < self.horizon).collect();
+
+        let mut max_intensity: f64 = 0.0;
+        let mut wet = 0.0;
+        let mut previous = (0.0, 0.0);
+        let curve = self.rain_curve();
+        for (time, rate) in curve.times.into_iter().zip(curve.rates) {
+            let time = time.min(self.horizon);
+            if previous.1 > 0.0 {
+                wet += time - previous.0;
+            }
+            if previous.0 < self.horizon {
+                max_intensity = max_intensity.max(previous.1);
+            }
+            previous = (time, rate);
+        }
+
+        StormStatistics {
+            storms: within.len(),
+            total_rain: self.rain_until(self.horizon),
+            max_intensity,
+            mean_duration: if within.is_empty() {
+                0.0
+            } else {
+                within.iter().map(|storm| storm.duration).sum::<f64>() / within.len() as f64
+            },
+            wet_fraction: if self.horizon > 0.0 { wet / self.horizon } else { 0.0 },
+        }
+    }
+}
+
+/// Piecewise constant rain rate of the sequence with the cumulative rain at each change
+#[derive(Debug, Default)]
+struct RainCurve {
+    times: Vec<f64>,
+    rates: Vec<f64>,
+    fallen: Vec<f64>,
+}
+
+impl RainCurve {
+    fn time_of_rain(&self, amount: f64) -> Option<f64> {
+        if amount <= 0.0 {
+            return Some(0.0);
+        }
+
+        // the rain falls between the change before the first one reaching the amount and it
+        let idx = self.fallen.partition_point(|fallen| *fallen < amount).checked_sub(1)?;
+        if idx + 1 == self.fallen.len() {
+            return None;
+        }
+
+        Some(self.times[idx] + (amount - self.fallen[idx]) / self.rates[idx])
+    }
+}
+
+fn is_positive_number(v: f64) -> bool {
+    v.is_finite() && v >= 0.0
+}
+
+/// Generator of storms arriving as a Poisson process with exponentially
+/// distributed durations and intensities
+#[derive(Debug, Clone, PartialEq)]
+pub struct StormGenerator {
+    arrival_rate: f64,
+    mean_duration: f64,
+    mean_intensity: f64,
+}
+
+impl StormGenerator {
+    /// `arrival_rate` is the mean number of storms per unit of time
+    pub fn new(arrival_rate: f64, mean_duration: f64, mean_intensity: f64) -> Result<Self, ModelError> {
+        if !is_positive_number(arrival_rate) || !is_positive_number(mean_duration) || !is_positive_number(mean_intensity) {
+            return Err(ModelError::InvalidStormParameters);
+        }
+
+        Ok(StormGenerator {
+            arrival_rate,
+            mean_duration,
+            mean_intensity,
+        })
+    }
+
+    /// Sample storms starting before the horizon, the same seed gives the same storms
+    pub fn generate(&self, horizon: f64, seed: u64) -> Result<StormSequence, ModelError> {
+        let mut rng = ChaCha8Rng::seed_from_u64(seed);
+        let exp = |mean: f64| Exp::new(1.0 / mean).map_err(|_| ModelError::InvalidStormParameters);
+
+        let mut storms = Vec::new();
+        if self.arrival_rate > 0.0 && is_positive_number(horizon) {
+            let arrivals = Exp::new(self.arrival_rate).map_err(|_| ModelError::InvalidStormParameters)?;
+            let durations = if self.mean_duration > 0.0 { Some(exp(self.mean_duration)?) } else { None };
+            let intensities = if self.mean_intensity > 0.0 { Some(exp(self.mean_intensity)?) } else { None };
+
+            let mut start = arrivals.sample(&mut rng);
+            while start < horizon {
+                storms.push(Storm {
+                    start,
+                    duration: durations.map_or(0.0, |durations| durations.sample(&mut rng)),
+                    intensity: intensities.map_or(0.0, |intensities| intensities.sample(&mut rng)),
+                });
+                start += arrivals.sample(&mut rng);
+            }
+        }
+
+        StormSequence::new(storms, horizon)
+    }
+}
+
+/// Levels and merges of the model under the storms
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct StormRun {
+    times: Vec<f64>,
+    levels: Vec<Vec<f64>>,
+    merge_times: Vec<f64>,
+}
+
+impl StormRun {
+    /// Requested times
+    pub fn times(&self) -> &[f64] {
+        &self.times
+    }
+
+    /// Levels of all the columns at each requested time
+    pub fn levels(&self) -> &[Vec<f64>] {
+        &self.levels
+    }
+
+    /// Times of the configuration changes, i.e. basins reaching their rims, within the horizon of the storms
+    pub fn merge_times(&self) -> &[f64] {
+        &self.merge_times
+    }
+}
+
+impl Model<f64> {
+    /// Calculate levels under the storms instead of the constant rain
+    ///
+    /// Levels depend only on the amount of water fallen so far, so the model is evaluated
+    /// at the time it receives the same amount with its own rain rate. The max time
+    /// of the model should be long enough for the rain of the storms.
+    pub fn run_storms(&self, storms: &StormSequence, times: &[f64]) -> Result<StormRun, ModelError> {
+        let rain_rate = self.config().rain_rate;
+        if rain_rate <= 0.0 {
+            return Err(ModelError::InvalidRainRate);
+        }
+
+        let levels = times.iter()
+            .map(|time| {
+                if !time.is_finite() {
+                    return Err(ModelError::InvalidTime);
+                }
+                if *time < 0.0 {
+                    return Err(ModelError::NegativeTime);
+                }
+                self.calculate_levels(storms.rain_until(*time) / rain_rate)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let curve = storms.rain_curve();
+        let merge_times = self.generations()
+            .iter()
+            .skip(1)
+            .map(|generation| generation.start)
+            .take_while(|start| *start <= *self.max_time())
+            .filter_map(|start| curve.time_of_rain(start * rain_rate))
+            .filter(|time| *time <= storms.horizon())
+            .collect();
+
+        Ok(StormRun {
+            times: times.to_vec(),
+            levels,
+            merge_times,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_abs_diff_eq;
+
+    use super::*;
+
+    #[test]
+    fn test_generator() {
+        let generator = StormGenerator::new(2.0, 0.25, 3.0).unwrap();
+        let sequence = generator.generate(1000.0, 42).unwrap();
+
+        assert_eq!(sequence, generator.generate(1000.0, 42).unwrap());
+        assert_ne!(sequence, generator.generate(1000.0, 43).unwrap());
+
+        let statistics = sequence.statistics();
+        assert!((statistics.storms() as f64 - 2000.0).abs() < 150.0);
+        assert_abs_diff_eq!(statistics.mean_duration(), 0.25, epsilon = 0.025);
+        // each storm brings 0.75 on average
+        assert_abs_diff_eq!(statistics.total_rain(), 2000.0 * 0.75, epsilon = 200.0);
+        assert!(statistics.wet_fraction() > 0.0 && statistics.wet_fraction() < 1.0);
+        assert!(statistics.max_intensity() > 3.0);
+
+        for pair in sequence.storms().windows(2) {
+            assert!(pair[0].start() <= pair[1].start());
+        }
+        assert!(sequence.storms().iter().all(|storm| storm.start() < 1000.0));
+    }
+
+    #[test]
+    fn test_rain() {
+        let sequence = StormSequence::new(vec![Storm::new(3.0, 1.0, 1.0), Storm::new(1.0, 3.0, 2.0)], 10.0).unwrap();
+
+        assert_eq!(sequence.rain_until(1.0), 0.0);
+        assert_eq!(sequence.rain_until(2.0), 2.0);
+        assert_eq!(sequence.rain_until(3.5), 5.5);
+        assert_eq!(sequence.rain_until(10.0), 7.0);
+
+        assert_eq!(sequence.time_of_rain(0.0), Some(0.0));
+        assert_eq!(sequence.time_of_rain(2.0), Some(2.0));
+        assert_eq!(sequence.time_of_rain(5.5), Some(3.5));
+        assert_eq!(sequence.time_of_rain(7.5), None);
+
+        let statistics = sequence.statistics();
+        assert_eq!(statistics.storms(), 2);
+        assert_eq!(statistics.total_rain(), 7.0);
+        assert_eq!(statistics.max_intensity(), 3.0);
+        assert_eq!(statistics.mean_duration(), 2.0);
+        assert_eq!(statistics.wet_fraction(), 0.3);
+    }
+
+    #[test]
+    fn test_dry_gap_after_overlap() {
+        // adding and subtracting 0.1 and 0.2 leaves a tiny positive residue
+        let sequence = StormSequence::new(
+            vec![Storm::new(0.0, 2.0, 0.1), Storm::new(1.0, 2.0, 0.2), Storm::new(5.0, 1.0, 0.3)],
+            10.0,
+        ).unwrap();
+
+        assert_abs_diff_eq!(sequence.statistics().wet_fraction(), 0.4, epsilon = 1e-12);
+        assert_abs_diff_eq!(sequence.time_of_rain(0.65).unwrap(), 5.0 + 0.05 / 0.3, epsilon = 1e-12);
+        assert_eq!(sequence.time_of_rain(1.0), None);
+    }
+
+    #[test]
+    fn test_long_sequence() {
+        let sequence = StormGenerator::new(5.0, 0.5, 1.0).unwrap().generate(10000.0, 7).unwrap();
+        let total = sequence.rain_until(sequence.horizon());
+
+        for fraction in [0.001, 0.25, 0.5, 0.999] {
+            let time = sequence.time_of_rain(total * fraction).unwrap();
+            assert_abs_diff_eq!(sequence.rain_until(time), total * fraction, epsilon = 1e-6 * total);
+        }
+    }
+
+    #[test]
+    fn test_run_storms() {
+        let model = Model::new(&[3.0, 1.0, 6.0, 4.0, 8.0, 9.0], 20.0).unwrap();
+        let sequence = StormSequence::new(vec![Storm::new(1.0, 2.0, 0.5)], 5.0).unwrap();
+
+        let run = model.run_storms(&sequence, &[0.5, 2.0, 5.0]).unwrap();
+        assert_eq!(run.levels()[0], model.calculate_levels(0.0).unwrap());
+        assert_eq!(run.levels()[1], model.calculate_levels(0.5).unwrap());
+        assert_eq!(run.levels()[2], model.calculate_levels(1.0).unwrap());
+
+        // the right basin reaches the wall at 6.0, when 2 / 3.5 of rain falls
+        assert_eq!(run.merge_times().len(), 2);
+        assert_abs_diff_eq!(run.merge_times()[0], 1.0 + 2.0 / 3.5 / 0.5);
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(StormGenerator::new(-1.0, 1.0, 1.0), Err(ModelError::InvalidStormParameters));
+        assert_eq!(StormGenerator::new(1.0, f64::NAN, 1.0), Err(ModelError::InvalidStormParameters));
+        assert_eq!(StormSequence::new(vec![Storm::new(1.0, 1.0, -1.0)], 1.0), Err(ModelError::InvalidStormParameters));
+
+        let model = Model::new(&[3.0, 1.0, 6.0], 1.0).unwrap();
+        let sequence = StormSequence::new(vec![Storm::new(0.0, 2.0, 1.0)], 2.0).unwrap();
+        assert_eq!(model.run_storms(&sequence, &[2.0]), Err(ModelError::TimeOutOfHorizon));
+        assert_eq!(model.run_storms(&sequence, &[-0.5]), Err(ModelError::NegativeTime));
+        assert_eq!(model.run_storms(&sequence, &[f64::NAN]), Err(ModelError::InvalidTime));
+    }
+}
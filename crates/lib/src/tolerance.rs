@@ -0,0 +1,82 @@
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::scalar::Scalar;
+
+/// Policy used to check if two heights or two event times are equal
+///
+/// Heights closer than the merge tolerance are merged into a single part,
+/// events closer than the time tolerance are treated as simultaneous,
+/// see `ModelBuilder`.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Tolerance<T = f64> {
+    /// Values are equal if they differ by no more than the provided amount
+    Absolute(T),
+
+    /// Values are equal if they differ by no more than the provided
+    /// fraction of the larger magnitude
+    Relative(T),
+}
+
+fn abs<T: Scalar>(v: &T) -> T {
+    if v.is_negative() {
+        T::zero() - v.clone()
+    } else {
+        v.clone()
+    }
+}
+
+impl<T: Scalar> Tolerance<T> {
+    /// Check if two values are equal according to the policy
+    pub fn is_equal(&self, a: &T, b: &T) -> bool {
+        let diff = abs(&(a.clone() - b.clone()));
+
+        match self {
+            Tolerance::Absolute(amount) => diff <= *amount,
+            Tolerance::Relative(fraction) => {
+                let (a, b) = (abs(a), abs(b));
+                let largest = if a > b { a } else { b };
+                diff <= fraction.clone() * largest
+            }
+        }
+    }
+
+    pub(crate) fn is_valid(&self) -> bool {
+        let value = match self {
+            Tolerance::Absolute(amount) => amount,
+            Tolerance::Relative(fraction) => fraction,
+        };
+
+        value.is_finite() && !value.is_negative()
+    }
+}
+
+impl<T: Scalar> Default for Tolerance<T> {
+    fn default() -> Self {
+        Tolerance::Absolute(T::default_tolerance())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_absolute() {
+        let tolerance = Tolerance::Absolute(0.1);
+        assert!(tolerance.is_equal(&1.0, &1.05));
+        assert!(tolerance.is_equal(&1.05, &1.0));
+        assert!(!tolerance.is_equal(&1000.0, &1000.5));
+        assert!(!tolerance.is_equal(&f64::NAN, &f64::NAN));
+    }
+
+    #[test]
+    fn test_relative() {
+        let tolerance = Tolerance::Relative(1e-3);
+        assert!(tolerance.is_equal(&1000.0, &1000.5));
+        assert!(!tolerance.is_equal(&1.0, &1.05));
+        assert!(tolerance.is_equal(&0.0, &0.0));
+        assert!(!tolerance.is_equal(&0.0, &1e-12));
+    }
+}
@@ -0,0 +1,290 @@
+use alloc::vec;
+use alloc::vec::Vec;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::error::ModelError;
+use crate::lakes::Lake;
+use crate::model::{Generation, Model};
+use crate::scalar::Scalar;
+
+/// Lake along with the amount of water of each tag stored in it
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct TracedLake<T = f64> {
+    lake: Lake<T>,
+    amounts: Vec<T>,
+}
+
+impl<T: Scalar> TracedLake<T> {
+    pub fn lake(&self) -> &Lake<T> {
+        &self.lake
+    }
+
+    /// Volume of the water of each tag in the order of `Tracers::tags`,
+    /// they sum up to the volume of the lake
+    pub fn amounts(&self) -> &[T] {
+        &self.amounts
+    }
+
+    /// Fraction of the water of each tag in the order of `Tracers::tags`
+    pub fn concentrations(&self) -> Vec<T> {
+        let volume = self.lake.volume();
+        self.amounts.iter().map(|amount| amount.clone() / volume.clone()).collect()
+    }
+}
+
+/// Passive tracers carried by the rain from the tagged columns into the lakes
+///
+/// Water reaching a lake mixes with all the water stored in it, lakes mix their
+/// tracers when they merge. The water running over the rim of a spilling lake does not
+/// mix with it and reaches the lakes downstream with the tags it came with, so the
+/// spilling lake keeps the composition it had, when it filled up.
+#[derive(Debug)]
+pub struct Tracers<'a, T = f64> {
+    model: &'a Model<T>,
+
+    /// distinct tags in ascending order
+    tags: Vec<usize>,
+
+    /// index of the tag of each column among the distinct ones
+    indices: Vec<usize>,
+}
+
+/// Amount of each tag reaching each part of the generation per unit of time
+fn inflows<T: Scalar>(generation: &Generation<T>, tags: &[usize], num_tags: usize, rain_rate: &T) -> Vec<Vec<T>> {
+    let parts = &generation.parts;
+    let mut inflows = vec![vec![T::zero(); num_tags]; parts.as_ref().len()];
+
+    for (idx, part) in parts.as_ref().iter().enumerate() {
+        // same routing as in `calculate_filling_velocity`
+        let destinations = if parts.accepts_water(idx) {
+            vec![(idx, T::from_usize(1))]
+        } else {
+            match parts.runoff_destinations(idx) {
+                (Some(left), Some(right)) => {
+                    let half = T::from_usize(1) / T::from_usize(2);
+                    vec![(left, half.clone()), (right, half)]
+                }
+                (Some(single), None) | (None, Some(single)) => vec![(single, T::from_usize(1))],
+                (None, None) => vec![],
+            }
+        };
+
+        for (destination, share) in destinations {
+            for column in part.range() {
+                let tag = tags[column];
+                inflows[destination][tag] = inflows[destination][tag].clone() + share.clone() * rain_rate.clone();
+            }
+        }
+    }
+
+    inflows
+}
+
+fn advance<T: Scalar>(amounts: &[Vec<T>], inflows: &[Vec<T>], duration: &T) -> Vec<Vec<T>> {
+    amounts.iter()
+        .zip(inflows)
+        .map(|(amounts, inflows)| {
+            amounts.iter()
+                .zip(inflows)
+                .map(|(amount, inflow)| amount.clone() + inflow.clone() * duration.clone())
+                .collect()
+        })
+        .collect()
+}
+
+/// Amounts of the parts of the next generation, merged parts get the tags of the parts they consist of
+fn merge<T: Scalar>(generation: &Generation<T>, next: &Generation<T>, at_end: &[Vec<T>], num_tags: usize) -> Vec<Vec<T>> {
+    let parts = generation.parts.as_ref();
+    let mut merged = Vec::with_capacity(next.parts.as_ref().len());
+    let mut old = 0;
+    for part in next.parts.as_ref() {
+        let mut sum = vec![T::zero(); num_tags];
+        while old < parts.len() && parts[old].range().end <= part.range().end {
+            for (sum, amount) in sum.iter_mut().zip(&at_end[old]) {
+                *sum = sum.clone() + amount.clone();
+            }
+            old += 1;
+        }
+        merged.push(sum);
+    }
+    merged
+}
+
+impl<'a, T: Scalar> Tracers<'a, T> {
+    /// Distinct tags in ascending order, amounts of the lakes follow this order
+    pub fn tags(&self) -> &[usize] {
+        &self.tags
+    }
+
+    /// Find all the lakes at the time along with the tags of their water, see `Model::lakes_at`
+    ///
+    /// Amounts are replayed over the generations up to the time, so only the amounts
+    /// of the current generation are kept in memory
+    pub fn lakes_at(&self, time: T) -> Result<Vec<TracedLake<T>>, ModelError> {
+        let lakes = self.model.lakes_at(time.clone())?;
+
+        let num_tags = self.tags.len();
+        let rain_rate = &self.model.config().rain_rate;
+        let generations = self.model.generations();
+        let first = generations.first().ok_or(ModelError::Inconsistency("no generations"))?;
+
+        let mut amounts = vec![vec![T::zero(); num_tags]; first.parts.as_ref().len()];
+        for (idx, generation) in generations.iter().enumerate() {
+            let inflows = inflows(generation, &self.indices, num_tags, rain_rate);
+
+            match (generations.get(idx + 1), &generation.end) {
+                (Some(next), Some(end)) if next.start <= time => {
+                    let at_end = advance(&amounts, &inflows, &(end.clone() - generation.start.clone()));
+                    amounts = merge(generation, next, &at_end, num_tags);
+                }
+                _ => {
+                    let amounts = advance(&amounts, &inflows, &(time - generation.start.clone()));
+                    let parts = generation.parts.as_ref();
+
+                    return lakes.into_iter()
+                        .map(|lake| {
+                            let part = parts.partition_point(|part| part.range().end <= lake.range().start);
+                            let amounts = amounts.get(part)
+                                .cloned()
+                                .ok_or(ModelError::Inconsistency("no part covers the lake"))?;
+
+                            Ok(TracedLake { lake, amounts })
+                        })
+                        .collect();
+                }
+            }
+        }
+
+        Err(ModelError::Inconsistency("no generation at the time"))
+    }
+}
+
+impl<T: Scalar> Model<T> {
+    /// Track the rain falling on the columns with the provided tags
+    ///
+    /// Tags may be arbitrary, amounts are reported only for the tags present, see `Tracers::tags`
+    pub fn tracers(&self, tags: &[usize]) -> Result<Tracers<'_, T>, ModelError> {
+        let num_columns = self.initial_parts().num_columns();
+        if tags.len() != num_columns {
+            return Err(ModelError::ColumnCountMismatch { expected: num_columns, actual: tags.len() });
+        }
+
+        // tags are replaced with their indices among the distinct ones
+        let mut distinct = tags.to_vec();
+        distinct.sort_unstable();
+        distinct.dedup();
+        let indices: Vec<usize> = tags.iter()
+            .map(|tag| distinct.binary_search(tag).unwrap_or_else(|idx| idx))
+            .collect();
+
+        Ok(Tracers {
+            model: self,
+            tags: distinct,
+            indices,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_abs_diff_eq;
+
+    use super::*;
+
+    const HEIGHTS: [f64; 6] = [3.0, 1.0, 6.0, 4.0, 8.0, 9.0];
+    const TAGS: [usize; 6] = [0, 0, 1, 1, 1, 1];
+
+    #[test]
+    fn test_filling() {
+        let model = Model::new(&HEIGHTS, 20.0).unwrap();
+        let tracers = model.tracers(&TAGS).unwrap();
+
+        assert!(tracers.lakes_at(0.0).unwrap().is_empty());
+
+        // the left basin gets the rain of its own field and a half of the rain on the wall at 6.0
+        let lakes = tracers.lakes_at(0.5).unwrap();
+        assert_eq!(lakes.len(), 2);
+        assert_abs_diff_eq!(lakes[0].concentrations()[0], 0.8, epsilon = 1e-12);
+        assert_abs_diff_eq!(lakes[0].concentrations()[1], 0.2, epsilon = 1e-12);
+        assert_eq!(lakes[1].concentrations(), vec![0.0, 1.0]);
+    }
+
+    #[test]
+    fn test_spilling() {
+        let model = Model::new(&HEIGHTS, 20.0).unwrap();
+        let tracers = model.tracers(&TAGS).unwrap();
+
+        // the right lake spills over the wall at 4 / 7 and keeps its water
+        let lakes = tracers.lakes_at(0.6).unwrap();
+        assert_eq!(lakes[1].lake().range(), 2..4);
+        assert_eq!(lakes[1].concentrations(), vec![0.0, 1.0]);
+
+        // the rain on the right field reaches the left lake
+        let before = tracers.lakes_at(0.55).unwrap()[0].amounts()[1];
+        let after = tracers.lakes_at(0.6).unwrap()[0].amounts()[1];
+        assert!(after - before > 0.05 * 0.5);
+    }
+
+    #[test]
+    fn test_conservation() {
+        let model = Model::new(&HEIGHTS, 20.0).unwrap();
+        let tracers = model.tracers(&TAGS).unwrap();
+
+        for time in [0.1, 0.5, 0.6, 0.7, 1.0, 2.0, 5.0, 20.0] {
+            for lake in tracers.lakes_at(time).unwrap() {
+                let total: f64 = lake.amounts().iter().sum();
+                assert_abs_diff_eq!(total, lake.lake().volume(), epsilon = 1e-9);
+            }
+        }
+
+        // all the rain is mixed in a single lake in the end
+        let lakes = tracers.lakes_at(20.0).unwrap();
+        assert_eq!(lakes.len(), 1);
+        assert_abs_diff_eq!(lakes[0].amounts()[0], 2.0 * 20.0, epsilon = 1e-9);
+        assert_abs_diff_eq!(lakes[0].concentrations()[0], 1.0 / 3.0, epsilon = 1e-12);
+    }
+
+    #[test]
+    fn test_tag_per_column() {
+        let heights: Vec<f64> = (0..300).map(|column| ((column * 37) % 23) as f64).collect();
+        let model = Model::new(&heights, 50.0).unwrap();
+        let tags: Vec<usize> = (0..heights.len()).collect();
+        let tracers = model.tracers(&tags).unwrap();
+
+        for time in [1.0, 10.0, 50.0] {
+            for lake in tracers.lakes_at(time).unwrap() {
+                let total: f64 = lake.amounts().iter().sum();
+                assert_abs_diff_eq!(total, lake.lake().volume(), epsilon = 1e-6);
+            }
+        }
+    }
+
+    #[test]
+    fn test_errors() {
+        let model = Model::new(&HEIGHTS, 20.0).unwrap();
+
+        assert!(matches!(model.tracers(&[0, 1]), Err(ModelError::ColumnCountMismatch { expected: 6, actual: 2 })));
+        assert_eq!(model.tracers(&TAGS).unwrap().lakes_at(21.0), Err(ModelError::TimeOutOfHorizon));
+    }
+
+    #[test]
+    fn test_large_tags() {
+        let model = Model::new(&HEIGHTS, 20.0).unwrap();
+        let tags = [usize::MAX, usize::MAX, 1 << 40, 1 << 40, 1 << 40, 1 << 40];
+        let tracers = model.tracers(&tags).unwrap();
+
+        // the same split as with the small tags, in the order of the tags
+        assert_eq!(tracers.tags(), &[1 << 40, usize::MAX]);
+        let lakes = tracers.lakes_at(0.5).unwrap();
+        assert_abs_diff_eq!(lakes[0].concentrations()[1], 0.8, epsilon = 1e-12);
+        assert_abs_diff_eq!(lakes[0].concentrations()[0], 0.2, epsilon = 1e-12);
+        assert_eq!(lakes[1].concentrations(), vec![1.0, 0.0]);
+
+        let tracers = model.tracers(&[7, 7, 3, 3, 3, 3]).unwrap();
+        assert_eq!(tracers.tags(), &[3, 7]);
+        assert_eq!(tracers.lakes_at(0.5).unwrap()[0].amounts().len(), 2);
+    }
+}
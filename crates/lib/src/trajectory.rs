@@ -0,0 +1,121 @@
+use alloc::vec::Vec;
+use core::ops::Range;
+
+use crate::error::ModelError;
+use crate::model::Model;
+use crate::scalar::Scalar;
+
+impl<T: Scalar> Model<T> {
+    /// Calculate the level of the column as a piecewise linear function of time
+    ///
+    /// Returns `(time, level)` breakpoints from zero up to the max time, the level
+    /// is linear between each pair of consecutive breakpoints. Breakpoints are added only
+    /// where the slope changes, so merges of other parts do not show up.
+    pub fn column_trajectory(&self, column: usize) -> Result<Vec<(T, T)>, ModelError> {
+        let num_columns = self.initial_parts().num_columns();
+        if column >= num_columns {
+            return Err(ModelError::RangeOutOfBounds { range: column..column + 1, num_columns });
+        }
+
+        let max_time = self.max_time();
+        let mut breakpoints: Vec<(T, T)> = Vec::new();
+        let mut last = None;
+
+        for segment in self.column_segments(column) {
+            if segment.start > *max_time {
+                break;
+            }
+
+            if last.as_ref() != Some(&segment.rate) {
+                breakpoints.push((segment.start.clone(), segment.level.clone()));
+            }
+            last = Some(segment.rate.clone());
+
+            let reaches_end = segment.end.as_ref().is_none_or(|end| *end >= *max_time);
+            if reaches_end {
+                if breakpoints.last().is_some_and(|(time, _)| *time < *max_time) {
+                    breakpoints.push((max_time.clone(), segment.level_at(max_time)));
+                }
+                break;
+            }
+        }
+
+        Ok(breakpoints)
+    }
+
+    /// Calculate breakpoints of the levels of all the columns, see `column_trajectory`
+    ///
+    /// Columns of the same initial part share their trajectory, so it is calculated once for each of them
+    pub fn trajectories(&self) -> Result<Vec<Vec<(T, T)>>, ModelError> {
+        let mut trajectories = Vec::with_capacity(self.initial_parts().num_columns());
+
+        for part in self.initial_parts().as_ref() {
+            let Range { start, end } = part.range();
+            let trajectory = self.column_trajectory(start)?;
+            for _ in start..end {
+                trajectories.push(trajectory.clone());
+            }
+        }
+
+        Ok(trajectories)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_abs_diff_eq;
+
+    use super::*;
+
+    #[test]
+    fn test_column_trajectory() {
+        let model = Model::new(&[3.0, 1.0, 6.0, 4.0, 8.0, 9.0], 20.0).unwrap();
+        let trajectory = model.column_trajectory(3).unwrap();
+
+        // the right basin fills up to the wall at 6.0, then rises along with the left one
+        assert_eq!(trajectory[0], (0.0, 4.0));
+        assert_abs_diff_eq!(trajectory[1].0, 2.0 / 3.5);
+        assert_abs_diff_eq!(trajectory[1].1, 6.0);
+        assert_eq!(trajectory.last().unwrap().0, 20.0);
+
+        for pair in trajectory.windows(2) {
+            assert!(pair[0].0 < pair[1].0);
+            assert!(pair[0].1 <= pair[1].1);
+
+            // levels are linear between the breakpoints
+            let middle = (pair[0].0 + pair[1].0) / 2.0;
+            let expected = (pair[0].1 + pair[1].1) / 2.0;
+            assert_abs_diff_eq!(model.calculate_levels(middle).unwrap()[3], expected, epsilon = 1e-9);
+        }
+
+        for (time, level) in &trajectory {
+            assert_abs_diff_eq!(model.calculate_levels(*time).unwrap()[3], *level, epsilon = 1e-9);
+        }
+
+        assert_eq!(
+            model.column_trajectory(6),
+            Err(ModelError::RangeOutOfBounds { range: 6..7, num_columns: 6 }),
+        );
+    }
+
+    #[test]
+    fn test_trajectories() {
+        let model = Model::new(&[2.0, 2.0, 1.0, 5.0], 2.0).unwrap();
+        let trajectories = model.trajectories().unwrap();
+
+        assert_eq!(trajectories.len(), 4);
+        assert_eq!(trajectories[0], trajectories[1]);
+        for (column, trajectory) in trajectories.iter().enumerate() {
+            assert_eq!(trajectory, &model.column_trajectory(column).unwrap());
+        }
+
+        // the peak is not covered within the horizon and stays dry
+        assert_eq!(trajectories[3], vec![(0.0, 5.0), (2.0, 5.0)]);
+    }
+
+    #[test]
+    fn test_zero_horizon() {
+        let model = Model::new(&[2.0, 1.0], 0.0).unwrap();
+        assert_eq!(model.column_trajectory(1).unwrap(), vec![(0.0, 1.0)]);
+    }
+}
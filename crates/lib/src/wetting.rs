@@ -0,0 +1,67 @@
+use alloc::vec::Vec;
+
+use crate::model::Model;
+use crate::scalar::Scalar;
+
+impl<T: Scalar> Model<T> {
+    /// Calculate the time at which standing water first appears above each column
+    ///
+    /// Basins accepting water get wet right away, other columns when a neighbouring
+    /// lake rises above them. A lake spilling over its rim does not wet the rim, since
+    /// the water does not stand above it. Columns staying dry within the max time get None.
+    pub fn arrival_times(&self) -> Vec<Option<T>> {
+        let max_time = self.max_time();
+
+        (0..self.initial_parts().num_columns())
+            .map(|column| {
+                self.column_segments(column)
+                    .take_while(|segment| segment.start <= *max_time)
+                    .find(|segment| segment.rate > T::zero())
+                    .map(|segment| segment.start)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_abs_diff_eq;
+
+    use super::*;
+
+    #[test]
+    fn test_arrival_times() {
+        let heights = [3.0, 1.0, 6.0, 4.0, 8.0, 9.0];
+        let model = Model::new(&heights, 20.0).unwrap();
+        let arrivals = model.arrival_times();
+
+        assert_eq!(arrivals[1], Some(0.0));
+        assert_eq!(arrivals[3], Some(0.0));
+
+        // the left basin collects all the rain, once the right lake spills into it at 4 / 7
+        assert_abs_diff_eq!(arrivals[0].unwrap(), 2.0 / 3.0, epsilon = 1e-12);
+
+        for (column, arrival) in arrivals.iter().enumerate() {
+            let arrival = arrival.unwrap();
+            let after = model.calculate_levels(arrival + 1e-6).unwrap();
+            assert!(after[column] > heights[column]);
+
+            if arrival > 0.0 {
+                let before = model.calculate_levels(arrival - 1e-6).unwrap();
+                assert_abs_diff_eq!(before[column], heights[column], epsilon = 1e-5);
+            }
+        }
+
+        // the wall at 6.0 stays dry while the right lake spills over it
+        assert!(arrivals[2].unwrap() > 2.0 / 3.5);
+    }
+
+    #[test]
+    fn test_dry() {
+        let model = Model::new(&[3.0, 1.0, 6.0, 4.0, 8.0, 9.0], 1.0).unwrap();
+        let arrivals = model.arrival_times();
+
+        assert_eq!(arrivals[4], None);
+        assert_eq!(arrivals[5], None);
+    }
+}